@@ -0,0 +1,51 @@
+use crate::scoring::{Bm25, Boosts};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn env_var<T: FromStr>(key: &str) -> Option<T> {
+  env::var(key).ok().and_then(|x| x.parse().ok())
+}
+
+/// Server settings, read from `TWIGS_*` environment variables
+pub struct Config {
+  /// Directory holding snapshots and logs (`TWIGS_DATA_DIR`, default "data")
+  pub data_dir: PathBuf,
+  /// Logged operations between two snapshots (`TWIGS_SNAPSHOT_INTERVAL`, default 1000)
+  pub snapshot_interval: usize,
+}
+
+impl Config {
+  pub fn from_env() -> Config {
+    Config {
+      data_dir: env::var_os("TWIGS_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("data")),
+      snapshot_interval: env_var("TWIGS_SNAPSHOT_INTERVAL").unwrap_or(1000),
+    }
+  }
+
+  /// Relevance tuning of an index (`TWIGS_<INDEX>_K1`, `TWIGS_<INDEX>_B`)
+  pub fn bm25(&self, name: &str) -> Bm25 {
+    let prefix = format!("TWIGS_{}", name.to_uppercase());
+    let default = Bm25::default();
+    Bm25 {
+      k1: env_var(&format!("{}_K1", prefix)).unwrap_or(default.k1),
+      b: env_var(&format!("{}_B", prefix)).unwrap_or(default.b),
+    }
+  }
+
+  /// Field weights of an index (`TWIGS_<INDEX>_BOOST_<FIELD>`, e.g.
+  /// `TWIGS_SCENE_BOOST_NAME`)
+  pub fn boosts(&self, name: &str) -> Boosts {
+    let mut boosts = Boosts::default();
+    let fields: Vec<String> = boosts.fields().map(String::from).collect();
+    for field in fields {
+      let key = format!("TWIGS_{}_BOOST_{}", name.to_uppercase(), field.to_uppercase());
+      if let Some(weight) = env_var(&key) {
+        boosts.set(&field, weight);
+      }
+    }
+    boosts
+  }
+}
@@ -0,0 +1,85 @@
+use std::vec::Vec;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Aliasable {
+  pub id: String,
+  pub name: String,
+  pub aliases: Option<Vec<String>>,
+}
+
+impl Aliasable {
+  /// Name and aliases, as fed to the tokenizer
+  pub fn text(&self, field: &'static str) -> Vec<Text> {
+    let mut text = vec![Text::new(field, &self.name)];
+    if let Some(aliases) = &self.aliases {
+      text.extend(aliases.iter().map(|x| Text::alias(field, x)));
+    }
+    text
+  }
+}
+
+/// A searchable string of a document
+pub struct Text<'a> {
+  /// One of `Document::TEXT_FIELDS`
+  pub field: &'static str,
+  pub value: &'a str,
+  /// Alternative name of an actor or label, weighs less than the name
+  pub alias: bool,
+}
+
+impl<'a> Text<'a> {
+  pub fn new(field: &'static str, value: &'a str) -> Text<'a> {
+    Text { field, value, alias: false }
+  }
+
+  pub fn alias(field: &'static str, value: &'a str) -> Text<'a> {
+    Text { field, value, alias: true }
+  }
+}
+
+/// Collects the ids of a list of labels or actors
+pub fn aliasable_ids(items: &[Aliasable]) -> Vec<String> {
+  items.iter().map(|x| x.id.clone()).collect()
+}
+
+/// Collects the text of a list of labels or actors, attributed to `field`
+pub fn aliasable_text<'a>(field: &'static str, items: &'a [Aliasable]) -> Vec<Text<'a>> {
+  items.iter().flat_map(|x| x.text(field)).collect()
+}
+
+/// A stored document attribute, as seen by filters and sorting
+pub enum Field<'a> {
+  Flag(bool),
+  Number(Option<i64>),
+  Text(&'a str),
+  Ref(Option<&'a str>),
+  Refs(Vec<&'a str>),
+}
+
+/// An entity type that can be kept in an `Index`.
+///
+/// The input is what gets POSTed by the client, the implementing type is
+/// the trimmed down version that is kept in memory.
+pub trait Document: Clone {
+  type Input;
+
+  fn from_input(input: &Self::Input) -> Self;
+
+  /// External id of the input
+  fn input_id(input: &Self::Input) -> &str;
+
+  /// External id of the stored document
+  fn id(&self) -> &str;
+
+  /// Names of the searchable fields, usable as `field:value` in queries
+  const TEXT_FIELDS: &'static [&'static str];
+
+  /// Strings of the input that should be searchable
+  fn text(input: &Self::Input) -> Vec<Text>;
+
+  /// Names `field` knows, usable for sorting
+  const FIELDS: &'static [&'static str];
+
+  /// Looks up an attribute for filtering and sorting
+  fn field(&self, name: &str) -> Option<Field>;
+}
@@ -0,0 +1,46 @@
+use std::cmp::min;
+use std::vec::Vec;
+
+/// Number of typos tolerated in a term, longer terms allow more
+pub fn max_edits(term: &str) -> usize {
+  match term.len() {
+    0..=2 => 0,
+    3..=5 => 1,
+    _ => 2,
+  }
+}
+
+/// Relevance multiplier of a match that is `distance` edits away
+pub fn weight(distance: usize) -> f32 {
+  1.0 / (1.0 + distance as f32)
+}
+
+/// Levenshtein distance between `a` and `b`, None if it exceeds `max`
+pub fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+  let a = a.as_bytes();
+  let b = b.as_bytes();
+
+  if a.len().max(b.len()) - a.len().min(b.len()) > max {
+    return None;
+  }
+
+  let mut prev: Vec<usize> = (0..=b.len()).collect();
+  let mut curr = vec![0; b.len() + 1];
+
+  for i in 1..=a.len() {
+    curr[0] = i;
+    let mut row_min = curr[0];
+    for j in 1..=b.len() {
+      let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+      curr[j] = min(min(prev[j] + 1, curr[j - 1] + 1), prev[j - 1] + cost);
+      row_min = min(row_min, curr[j]);
+    }
+    // Distances never shrink from one row to the next
+    if row_min > max {
+      return None;
+    }
+    std::mem::swap(&mut prev, &mut curr);
+  }
+
+  Some(prev[b.len()]).filter(|d| *d <= max)
+}
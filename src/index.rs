@@ -0,0 +1,1664 @@
+use crate::document::{Document, Field};
+use crate::fuzzy;
+use crate::parser::{Clause, Expr, Occur};
+use crate::query::{compare_sorted, sort_spec, Cursor, Filter, Query, Sort, SortValue};
+use crate::scoring::{Bm25, Boosts};
+use crate::tokenizer::{partial_word, positioned_words, spans, stem, tokenize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound;
+use std::vec::Vec;
+
+/// Positions between two searchable strings of a document, so phrases
+/// never match across e.g. two labels
+const STRING_GAP: u32 = 100;
+
+/// Occurrences of a token in one document
+#[derive(Serialize, Deserialize)]
+pub struct Posting {
+  pub id: u32,
+  pub fields: Vec<Occurrences>,
+}
+
+/// Occurrences of a token in the names or the aliases of one field
+#[derive(Serialize, Deserialize)]
+pub struct Occurrences {
+  /// Index into `Document::TEXT_FIELDS`
+  pub field: u8,
+  pub alias: bool,
+  /// Ascending token positions
+  pub positions: Vec<u32>,
+}
+
+impl Posting {
+  /// Occurrences in `field`, or in every field if `None`
+  pub fn occurrences(&self, field: Option<u8>) -> impl Iterator<Item = &Occurrences> {
+    self.fields.iter().filter(move |x| field.map_or(true, |field| x.field == field))
+  }
+}
+
+/// In-memory inverted index over one kind of document
+#[derive(Serialize, Deserialize)]
+pub struct Index<T: Document> {
+  id_map: HashMap<String, u32>,
+  docs: HashMap<u32, T>,
+  /// Internal id of the next new document, ids are never reused
+  next_id: u32,
+  /// Term dictionary, ordered for prefix lookups
+  tokens: BTreeMap<String, Vec<Posting>>,
+  /// Tokens each document has postings for
+  doc_tokens: HashMap<u32, Vec<String>>,
+  /// Unstemmed words and the number of documents containing them, used
+  /// for suggestions
+  words: BTreeMap<String, u32>,
+  /// Words each document contributed
+  doc_words: HashMap<u32, Vec<String>>,
+  /// Number of tokens per document
+  lengths: HashMap<u32, u32>,
+  total_length: u64,
+  #[serde(skip)]
+  bm25: Bm25,
+  #[serde(skip)]
+  boosts: Boosts,
+}
+
+#[derive(Serialize)]
+pub struct IndexInfo {
+  pub size: usize,
+  pub num_tokens: usize,
+  pub num_references: usize,
+  pub num_references_per_token: usize,
+}
+
+pub struct SearchResult<'a, T> {
+  pub num_hits: usize,
+  /// Highest relevance any document could reach for the query, 0 without
+  /// query text
+  pub max_score: f32,
+  pub items: Vec<Hit<'a, T>>,
+  /// Most common values of the requested facets among the hits
+  pub facets: BTreeMap<String, Vec<FacetCount>>,
+  /// Cursor for the next page, `None` on the last one
+  pub next: Option<String>,
+  /// Breakdown of the document in `Query::explain`, `None` if it is unknown
+  pub explanation: Option<Explanation>,
+}
+
+/// Why a document is or is not a hit of a search, and how its relevance
+/// came about
+#[derive(Serialize)]
+pub struct Explanation {
+  pub id: String,
+  /// Whether it matches the query text
+  pub matched: bool,
+  /// Place among all hits, from 0, `None` if it is not a hit
+  pub position: Option<usize>,
+  pub score: f32,
+  /// Breakdown of the score, `None` if it does not come from the query text
+  pub relevance: Option<ScoreDetail>,
+  /// Why the query text does not match it, e.g. the required clause it
+  /// lacks or the excluded one it has
+  pub reason: Option<String>,
+  pub filters: Vec<FilterCheck>,
+}
+
+/// A part of a relevance score, made of its details
+#[derive(Serialize)]
+pub struct ScoreDetail {
+  pub value: f32,
+  pub description: String,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub details: Vec<ScoreDetail>,
+}
+
+impl ScoreDetail {
+  fn new(value: f32, description: String) -> ScoreDetail {
+    ScoreDetail {
+      value,
+      description,
+      details: Vec::new(),
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct FilterCheck {
+  pub filter: String,
+  pub passed: bool,
+}
+
+/// A value of a document field, as counted by facets
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(untagged)]
+pub enum FacetValue {
+  Flag(bool),
+  Number(i64),
+  Text(String),
+}
+
+#[derive(Serialize)]
+pub struct FacetCount {
+  pub value: FacetValue,
+  pub count: usize,
+}
+
+pub struct Hit<'a, T> {
+  pub doc: &'a T,
+  /// Relevance, 0 without query text
+  pub score: f32,
+  /// Query terms found in the document, only recorded with `Query::with_scores`
+  pub matches: Vec<TermMatch>,
+  /// Text of the fields in `Query::highlight`, with the matched words marked
+  pub highlights: BTreeMap<String, String>,
+}
+
+/// A query term found in one field of a document
+#[derive(Clone, Serialize)]
+pub struct TermMatch {
+  /// Word or phrase of the query
+  pub term: String,
+  /// Indexed token that matched, differs from the term for typos and completions
+  pub token: String,
+  pub field: &'static str,
+  /// Whether it matched an alias rather than a name
+  pub alias: bool,
+}
+
+impl<'a, T> Hit<'a, T> {
+  /// Relevance relative to the best possible one, comparable between indexes
+  pub fn normalized_score(&self, max_score: f32) -> f32 {
+    if max_score > 0.0 {
+      self.score / max_score
+    } else {
+      0.0
+    }
+  }
+}
+
+/// Relevance of matching documents, along with the highest relevance any
+/// document could reach
+#[derive(Default)]
+struct Matches {
+  scores: HashMap<u32, f32>,
+  max_score: f32,
+  /// Terms found in each document, if requested
+  terms: HashMap<u32, Vec<TermMatch>>,
+  /// Breakdown of the score of the explained document, if it matches
+  explanation: Option<ScoreDetail>,
+  /// Why the explained document does not match, if it does not
+  reason: Option<String>,
+}
+
+impl<T: Document> Default for Index<T> {
+  fn default() -> Self {
+    Index::new()
+  }
+}
+
+impl<T: Document> Index<T> {
+  pub fn new() -> Index<T> {
+    Index {
+      id_map: HashMap::new(),
+      docs: HashMap::new(),
+      next_id: 0,
+      tokens: BTreeMap::new(),
+      doc_tokens: HashMap::new(),
+      words: BTreeMap::new(),
+      doc_words: HashMap::new(),
+      lengths: HashMap::new(),
+      total_length: 0,
+      bm25: Bm25::default(),
+      boosts: Boosts::default(),
+    }
+  }
+
+  pub fn set_bm25(&mut self, bm25: Bm25) {
+    self.bm25 = bm25;
+  }
+
+  pub fn set_boosts(&mut self, boosts: Boosts) {
+    self.boosts = boosts;
+  }
+
+  pub fn len(&self) -> usize {
+    self.docs.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.docs.is_empty()
+  }
+
+  pub fn contains(&self, id: &str) -> bool {
+    self.id_map.contains_key(id)
+  }
+
+  /// Adds a document, or replaces it if its id is already known
+  pub fn insert(&mut self, input: &T::Input) {
+    let external_id = T::input_id(input);
+    let id = match self.id_map.get(external_id) {
+      Some(&id) => id,
+      None => {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.id_map.insert(external_id.to_string(), id);
+        id
+      }
+    };
+
+    self.docs.insert(id, T::from_input(input));
+    self.index_text(input, id);
+  }
+
+  /// Replaces a known document, returns false if the id is unknown or
+  /// differs from the id of `input`
+  pub fn update(&mut self, id: &str, input: &T::Input) -> bool {
+    if T::input_id(input) != id {
+      return false;
+    }
+    match self.id_map.get(id) {
+      Some(&internal_id) => {
+        self.docs.insert(internal_id, T::from_input(input));
+        self.index_text(input, internal_id);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Removes a document, returns false if the id is unknown
+  pub fn remove(&mut self, id: &str) -> bool {
+    match self.id_map.remove(id) {
+      Some(internal_id) => {
+        self.docs.remove(&internal_id);
+        self.unindex(internal_id);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Removes several documents in one pass over their postings, returns
+  /// how many of the ids were known
+  pub fn remove_many(&mut self, ids: &[String]) -> usize {
+    let internal_ids: HashSet<u32> = ids.iter().filter_map(|id| self.id_map.remove(id)).collect();
+    for id in internal_ids.iter() {
+      self.docs.remove(id);
+    }
+    self.unindex_all(&internal_ids);
+    internal_ids.len()
+  }
+
+  pub fn clear(&mut self) {
+    self.docs.clear();
+    self.tokens.clear();
+    self.doc_tokens.clear();
+    self.words.clear();
+    self.doc_words.clear();
+    self.id_map.clear();
+    self.next_id = 0;
+    self.lengths.clear();
+    self.total_length = 0;
+    self.docs.shrink_to_fit();
+    self.doc_tokens.shrink_to_fit();
+    self.doc_words.shrink_to_fit();
+    self.id_map.shrink_to_fit();
+    self.lengths.shrink_to_fit();
+  }
+
+  pub fn info(&self) -> IndexInfo {
+    let num_references = self.tokens.values().map(|vec| vec.len()).sum();
+
+    IndexInfo {
+      size: self.docs.len(),
+      num_tokens: self.tokens.len(),
+      num_references,
+      num_references_per_token: if self.tokens.is_empty() {
+        0
+      } else {
+        num_references / self.tokens.len()
+      },
+    }
+  }
+
+  pub fn search(&self, query: &Query) -> SearchResult<T> {
+    let explain = query.explain.as_ref().and_then(|id| self.id_map.get(id)).copied();
+    // Only blank text returns everything, text without any word long
+    // enough to search, like "tv" or "!!!", matches nothing
+    let mut matches = query.expr.as_ref().map(|expr| {
+      self.score(expr, query, explain).unwrap_or_else(|| Matches {
+        reason: Some("the query has no words long enough to search".to_string()),
+        ..Matches::default()
+      })
+    });
+    let max_score = matches.as_ref().map_or(0.0, |x| x.max_score);
+    let relevance = matches.as_mut().and_then(|x| x.explanation.take());
+    let reason = matches.as_mut().and_then(|x| x.reason.take());
+    let mut hits: Vec<Hit<T>> = match matches {
+      None => self
+        .docs
+        .values()
+        .map(|doc| Hit {
+          doc,
+          score: 0.0,
+          matches: Vec::new(),
+          highlights: BTreeMap::new(),
+        })
+        .collect(),
+      Some(Matches { scores, mut terms, .. }) => scores
+        .into_iter()
+        .map(|(id, score)| Hit {
+          doc: &self.docs[&id],
+          score,
+          matches: terms.remove(&id).unwrap_or_default(),
+          highlights: BTreeMap::new(),
+        })
+        .collect(),
+    };
+
+    let facets = query
+      .facets
+      .iter()
+      .map(|name| (name.clone(), facet(name, &hits, query)))
+      .collect();
+
+    let mut explanation = explain.map(|id| {
+      let doc = &self.docs[&id];
+      let hit = hits.iter().find(|x| std::ptr::eq(x.doc, doc));
+      Explanation {
+        id: doc.id().to_string(),
+        matched: hit.is_some(),
+        position: None,
+        score: hit.map_or(0.0, |x| x.score),
+        relevance,
+        reason,
+        filters: query
+          .filters
+          .iter()
+          .map(|filter| FilterCheck {
+            filter: filter.to_string(),
+            passed: filter.matches(doc),
+          })
+          .collect(),
+      }
+    });
+
+    hits.retain(|hit| query.filters.iter().all(|filter| filter.matches(hit.doc)));
+
+    let relevance = [Sort::new("relevance", false)];
+    let sort = if query.sort.is_empty() { &relevance[..] } else { &query.sort[..] };
+    let mut hits: Vec<(Vec<SortValue>, Hit<T>)> = hits
+      .into_iter()
+      .map(|hit| (sort.iter().map(|key| key.value(hit.doc, hit.score)).collect(), hit))
+      .collect();
+    let num_hits = hits.len();
+
+    if let Some(explanation) = explanation.as_mut() {
+      if let Some((values, hit)) = hits.iter().find(|(_, hit)| hit.doc.id() == explanation.id) {
+        let target = (&values[..], hit.doc.id());
+        explanation.position = Some(
+          hits
+            .iter()
+            .filter(|(values, hit)| compare_sorted(sort, (values, hit.doc.id()), target) == Ordering::Less)
+            .count(),
+        );
+      }
+    }
+
+    if let Some(cursor) = &query.after {
+      let after = (&cursor.values[..], cursor.id.as_str());
+      hits.retain(|(values, hit)| compare_sorted(sort, (values, hit.doc.id()), after) == Ordering::Greater);
+    }
+
+    // Only the hits up to the end of the page need to be in order
+    let compare = |a: &(Vec<SortValue>, Hit<T>), b: &(Vec<SortValue>, Hit<T>)| {
+      compare_sorted(sort, (&a.0, a.1.doc.id()), (&b.0, b.1.doc.id()))
+    };
+    let end = query.skip.saturating_add(query.take);
+    let has_more = hits.len() > end;
+    if has_more {
+      hits.select_nth_unstable_by(end, compare);
+      hits.truncate(end);
+    }
+    hits.sort_by(compare);
+
+    let next = match hits.last() {
+      Some((values, hit)) if has_more => Some(
+        Cursor {
+          sort: sort_spec(&query.sort),
+          values: values.clone(),
+          id: hit.doc.id().to_string(),
+        }
+        .encode(),
+      ),
+      _ => None,
+    };
+
+    let mut items: Vec<Hit<T>> = hits.into_iter().skip(query.skip).map(|(_, hit)| hit).collect();
+    if !query.highlight.is_empty() {
+      let mut tokens = Vec::new();
+      if let Some(expr) = &query.expr {
+        self.highlight_tokens(expr, query, &mut tokens);
+      }
+      for hit in items.iter_mut() {
+        for name in query.highlight.iter() {
+          if let Some(text) = highlight(hit.doc, name, &tokens, query) {
+            hit.highlights.insert(name.clone(), text);
+          }
+        }
+      }
+    }
+
+    SearchResult {
+      num_hits,
+      max_score,
+      items,
+      facets,
+      next,
+      explanation,
+    }
+  }
+
+  /// Relevance of every document matching `expr`, `None` if the expression
+  /// does not restrict anything, e.g. because all its words are too short
+  fn score(&self, expr: &Expr, query: &Query, explain: Option<u32>) -> Option<Matches> {
+    match expr {
+      Expr::Term { field, word, partial } => {
+        let (word, field) = term_field::<T>(field, word);
+        self.score_term(&word, field, *partial && query.prefix, query, explain)
+      }
+      Expr::Phrase { field, phrase } => {
+        self.score_phrase(phrase, field.as_deref().and_then(field_id::<T>), query, explain)
+      }
+      Expr::Group(clauses) => self.score_group(clauses, query, explain),
+      Expr::Or(exprs) => {
+        let mut matches: Option<Matches> = None;
+        let mut details = Vec::new();
+        for expr_matches in exprs.iter().filter_map(|expr| self.score(expr, query, explain)) {
+          let matches = matches.get_or_insert_with(Matches::default);
+          details.extend(expr_matches.explanation);
+          for (id, score) in expr_matches.scores {
+            *matches.scores.entry(id).or_insert(0.0) += score;
+          }
+          for (id, terms) in expr_matches.terms {
+            matches.terms.entry(id).or_insert_with(Vec::new).extend(terms);
+          }
+          matches.max_score += expr_matches.max_score;
+        }
+        if let (Some(matches), Some(id)) = (matches.as_mut(), explain) {
+          match matches.scores.get(&id) {
+            Some(&score) => {
+              matches.explanation = Some(ScoreDetail {
+                value: score,
+                description: "sum of the matching alternatives".to_string(),
+                details,
+              })
+            }
+            None => matches.reason = Some("matches none of the alternatives".to_string()),
+          }
+        }
+        matches
+      }
+    }
+  }
+
+  /// Documents matching every required clause and no excluded one. Without
+  /// required clauses, at least one optional clause has to match.
+  fn score_group(&self, clauses: &[Clause], query: &Query, explain: Option<u32>) -> Option<Matches> {
+    let mut required = Vec::new();
+    let mut optional = Vec::new();
+    let mut excluded = Vec::new();
+    // First clause ruling out the explained document
+    let mut reason = None;
+    for clause in clauses {
+      if let Some(matches) = self.score(&clause.expr, query, explain) {
+        if let Some(id) = explain.filter(|_| reason.is_none()) {
+          reason = match clause.occur {
+            Occur::Must if !matches.scores.contains_key(&id) => Some(format!("lacks the required clause {}", clause)),
+            Occur::MustNot if matches.scores.contains_key(&id) => Some(format!("has the excluded clause {}", clause)),
+            _ => None,
+          };
+        }
+        match clause.occur {
+          Occur::Must => required.push(matches),
+          Occur::Should => optional.push(matches),
+          Occur::MustNot => excluded.push(matches),
+        }
+      }
+    }
+
+    let mut candidates: HashSet<u32> = if let Some((first, rest)) = required.split_first() {
+      first
+        .scores
+        .keys()
+        .filter(|id| rest.iter().all(|x| x.scores.contains_key(id)))
+        .copied()
+        .collect()
+    } else if !optional.is_empty() {
+      optional.iter().flat_map(|x| x.scores.keys()).copied().collect()
+    } else if !excluded.is_empty() {
+      // Only exclusions, everything else matches
+      self.docs.keys().copied().collect()
+    } else {
+      return None;
+    };
+    for matches in excluded.iter() {
+      candidates.retain(|id| !matches.scores.contains_key(id));
+    }
+
+    let mut terms: HashMap<u32, Vec<TermMatch>> = HashMap::new();
+    for matches in required.iter_mut().chain(optional.iter_mut()) {
+      for (id, found) in matches.terms.drain().filter(|(id, _)| candidates.contains(id)) {
+        terms.entry(id).or_insert_with(Vec::new).extend(found);
+      }
+    }
+
+    let details: Vec<ScoreDetail> = required
+      .iter_mut()
+      .chain(optional.iter_mut())
+      .filter_map(|x| x.explanation.take())
+      .collect();
+
+    let scoring: Vec<&Matches> = required.iter().chain(optional.iter()).collect();
+    let scores: HashMap<u32, f32> = candidates
+      .into_iter()
+      .map(|id| (id, scoring.iter().filter_map(|x| x.scores.get(&id)).sum()))
+      .collect();
+    let explanation = explain.and_then(|id| scores.get(&id)).map(|&score| ScoreDetail {
+      value: score,
+      description: "sum of the matching clauses".to_string(),
+      details,
+    });
+    if explain.map_or(false, |id| !scores.contains_key(&id)) && reason.is_none() {
+      reason = Some("matches none of the clauses".to_string());
+    }
+    Some(Matches {
+      scores,
+      max_score: scoring.iter().map(|x| x.max_score).sum(),
+      terms,
+      explanation,
+      reason,
+    })
+  }
+
+  /// Relevance of an unquoted word, only looking at `field` if given. A
+  /// word made of several tokens, like "anna-bell", is searched as a phrase.
+  fn score_term(&self, word: &str, field: Option<u8>, prefix: bool, query: &Query, explain: Option<u32>) -> Option<Matches> {
+    let words = positioned_words(word);
+    if words.len() > 1 {
+      return self.score_phrase(word, field, query, explain);
+    }
+
+    let word = single_word(words, word, prefix)?;
+    let term = stem(&word);
+    let prefix = if prefix { Some(word.as_str()) } else { None };
+    let avg_len = self.average_length();
+
+    // A document matching several spellings of a term only counts the best one
+    let mut matches = Matches {
+      scores: HashMap::new(),
+      max_score: self.bm25.max_score(self.term_idf(&term, field)),
+      terms: HashMap::new(),
+      explanation: None,
+      reason: None,
+    };
+    for (token, weight) in self.expand(&word, &term, query.fuzzy, prefix) {
+      let postings = &self.tokens[token];
+      let idf = self.idf(postings, field);
+      matches.max_score = matches.max_score.max(weight * self.bm25.max_score(idf));
+      for posting in postings.iter() {
+        let tf: f32 = posting
+          .occurrences(field)
+          .map(|x| self.boost(x, query) * x.positions.len() as f32)
+          .sum();
+        if tf == 0.0 {
+          continue;
+        }
+        let len = self.lengths.get(&posting.id).copied().unwrap_or(0);
+        let score = weight * self.bm25.score(idf, tf, len, avg_len);
+        let best = matches.scores.entry(posting.id).or_insert(0.0);
+        if score > *best {
+          *best = score;
+          if query.with_scores {
+            let found = posting
+              .occurrences(field)
+              .filter(|x| self.boost(x, query) > 0.0)
+              .map(|x| self.term_match(&word, token, x))
+              .collect();
+            matches.terms.insert(posting.id, found);
+          }
+          if explain == Some(posting.id) {
+            let description = if weight < 1.0 {
+              format!("term \"{}\" as \"{}\", weight {}", word, token, weight)
+            } else {
+              format!("term \"{}\" as \"{}\"", word, token)
+            };
+            let found: Vec<(&Occurrences, usize)> = posting.occurrences(field).map(|x| (x, x.positions.len())).collect();
+            let idf = self.explain_idf(token, field);
+            matches.explanation = Some(self.explain_bm25(posting.id, score, description, idf, &found, query));
+          }
+        }
+      }
+    }
+    Some(matches)
+  }
+
+  /// Relevance of quoted words, scored like a single term made of all of them
+  fn score_phrase(&self, phrase: &str, field: Option<u8>, query: &Query, explain: Option<u32>) -> Option<Matches> {
+    let tokens: Vec<String> = tokenize(phrase);
+    if tokens.is_empty() {
+      return None;
+    }
+    let idf: f32 = tokens.iter().map(|token| self.term_idf(token, field)).sum();
+    let avg_len = self.average_length();
+    let token = tokens.join(" ");
+
+    let mut matches = Matches {
+      scores: HashMap::new(),
+      max_score: self.bm25.max_score(idf),
+      terms: HashMap::new(),
+      explanation: None,
+      reason: None,
+    };
+    for (id, (tf, found)) in self.phrase_matches(phrase, field, query) {
+      let len = self.lengths.get(&id).copied().unwrap_or(0);
+      let score = self.bm25.score(idf, tf, len, avg_len);
+      matches.scores.insert(id, score);
+      if query.with_scores {
+        let found = found.iter().map(|(x, _)| self.term_match(phrase, &token, x)).collect();
+        matches.terms.insert(id, found);
+      }
+      if explain == Some(id) {
+        let idf = ScoreDetail {
+          value: idf,
+          description: "idf, sum over the words".to_string(),
+          details: tokens.iter().map(|x| self.explain_idf(x, field)).collect(),
+        };
+        let description = format!("phrase \"{}\" as \"{}\"", phrase, token);
+        matches.explanation = Some(self.explain_bm25(id, score, description, idf, &found, query));
+      }
+    }
+    Some(matches)
+  }
+
+  /// Inverse document frequency of a token, which may not be indexed at all
+  fn term_idf(&self, token: &str, field: Option<u8>) -> f32 {
+    match self.tokens.get(token) {
+      Some(postings) => self.idf(postings, field),
+      None => Bm25::idf(self.docs.len(), 0),
+    }
+  }
+
+  /// Inverse document frequency of a token, counting only documents that
+  /// have it in `field` if given
+  fn idf(&self, postings: &[Posting], field: Option<u8>) -> f32 {
+    Bm25::idf(self.docs.len(), document_frequency(postings, field))
+  }
+
+  /// Inverse document frequency of a token, with the numbers it comes from
+  fn explain_idf(&self, token: &str, field: Option<u8>) -> ScoreDetail {
+    let df = self.tokens.get(token).map_or(0, |x| document_frequency(x, field));
+    ScoreDetail::new(
+      Bm25::idf(self.docs.len(), df),
+      format!("idf of \"{}\", in {} of {} documents", token, df, self.docs.len()),
+    )
+  }
+
+  /// Breakdown of the BM25 `score` of a term found in a document
+  fn explain_bm25(
+    &self,
+    id: u32,
+    score: f32,
+    description: String,
+    idf: ScoreDetail,
+    found: &[(&Occurrences, usize)],
+    query: &Query,
+  ) -> ScoreDetail {
+    let tf: Vec<ScoreDetail> = found
+      .iter()
+      .map(|(occurrences, count)| {
+        let boost = self.boost(occurrences, query);
+        let field = T::TEXT_FIELDS[occurrences.field as usize];
+        let kind = if occurrences.alias { " alias" } else { "" };
+        ScoreDetail::new(
+          boost * *count as f32,
+          format!("{} in {}{} × boost {}", count, field, kind, boost),
+        )
+      })
+      .collect();
+    let len = self.lengths.get(&id).copied().unwrap_or(0);
+
+    ScoreDetail {
+      value: score,
+      description,
+      details: vec![
+        idf,
+        ScoreDetail {
+          value: tf.iter().map(|x| x.value).sum(),
+          description: "term frequency, weighted by field".to_string(),
+          details: tf,
+        },
+        ScoreDetail::new(
+          len as f32,
+          format!(
+            "document length, average {:.1}, k1 {}, b {}",
+            self.average_length(),
+            self.bm25.k1,
+            self.bm25.b
+          ),
+        ),
+      ],
+    }
+  }
+
+  /// Weight of a match in the given occurrences, preferring the weights of
+  /// the query over those of the index
+  fn boost(&self, occurrences: &Occurrences, query: &Query) -> f32 {
+    let name = if occurrences.alias {
+      "alias"
+    } else {
+      T::TEXT_FIELDS[occurrences.field as usize]
+    };
+    query.boosts.get(name).or_else(|| self.boosts.get(name)).unwrap_or(1.0)
+  }
+
+  /// Describes a match of `term` in the given occurrences of `token`
+  fn term_match(&self, term: &str, token: &str, occurrences: &Occurrences) -> TermMatch {
+    TermMatch {
+      term: term.to_string(),
+      token: token.to_string(),
+      field: T::TEXT_FIELDS[occurrences.field as usize],
+      alias: occurrences.alias,
+    }
+  }
+
+  /// Documents containing the words of `phrase` next to each other within
+  /// one string, along with how often they do, weighted by field, and how
+  /// often in each of the occurrences of the first word
+  #[allow(clippy::type_complexity)]
+  fn phrase_matches(&self, phrase: &str, field: Option<u8>, query: &Query) -> HashMap<u32, (f32, Vec<(&Occurrences, usize)>)> {
+    let mut matches = HashMap::new();
+    let words = positioned_words(phrase);
+    let first = match words.first() {
+      Some((pos, _)) => *pos,
+      None => return matches,
+    };
+
+    // Postings of every phrase word by document, with its offset in the phrase
+    let mut word_postings: Vec<(u32, HashMap<u32, &Posting>)> = Vec::new();
+    for (pos, word) in words.iter() {
+      match self.tokens.get(&stem(word)) {
+        Some(postings) => word_postings.push((pos - first, postings.iter().map(|x| (x.id, x)).collect())),
+        None => return matches,
+      }
+    }
+
+    let (head, rest) = word_postings.split_first().unwrap();
+    for (id, posting) in head.1.iter() {
+      let mut count = 0.0;
+      let mut found_in = Vec::new();
+      for occurrences in posting.occurrences(field) {
+        let found = occurrences
+          .positions
+          .iter()
+          .filter(|start| {
+            rest.iter().all(|(offset, by_doc)| match by_doc.get(id) {
+              Some(posting) => posting
+                .occurrences(Some(occurrences.field))
+                .any(|x| x.positions.binary_search(&(*start + offset)).is_ok()),
+              None => false,
+            })
+          })
+          .count();
+        if found > 0 {
+          count += self.boost(occurrences, query) * found as f32;
+          found_in.push((occurrences, found));
+        }
+      }
+      if count > 0.0 {
+        matches.insert(*id, (count, found_in));
+      }
+    }
+    matches
+  }
+
+  /// Collects the indexed tokens that `expr` matches, along with the field
+  /// they have to be in. Words of excluded clauses are left out.
+  fn highlight_tokens(&self, expr: &Expr, query: &Query, tokens: &mut Vec<(Option<u8>, String)>) {
+    match expr {
+      Expr::Term { field, word, partial } => {
+        let (word, field) = term_field::<T>(field, word);
+        let prefix = *partial && query.prefix;
+        let words = positioned_words(&word);
+        if words.len() > 1 {
+          tokens.extend(words.iter().map(|(_, word)| (field, stem(word))));
+          return;
+        }
+        if let Some(word) = single_word(words, &word, prefix) {
+          let term = stem(&word);
+          let prefix = if prefix { Some(word.as_str()) } else { None };
+          for (token, _) in self.expand(&word, &term, query.fuzzy, prefix) {
+            tokens.push((field, token.to_string()));
+          }
+        }
+      }
+      Expr::Phrase { field, phrase } => {
+        let field = field.as_deref().and_then(field_id::<T>);
+        tokens.extend(tokenize(phrase).into_iter().map(|token| (field, token)));
+      }
+      Expr::Group(clauses) => {
+        for clause in clauses.iter().filter(|x| x.occur != Occur::MustNot) {
+          self.highlight_tokens(&clause.expr, query, tokens);
+        }
+      }
+      Expr::Or(exprs) => {
+        for expr in exprs {
+          self.highlight_tokens(expr, query, tokens);
+        }
+      }
+    }
+  }
+
+  /// Indexed tokens matching a query word and its stem `term`, along with
+  /// the weight of the match
+  fn expand(&self, word: &str, term: &str, fuzzy: bool, prefix: Option<&str>) -> Vec<(&str, f32)> {
+    let mut matches = Vec::new();
+
+    if let Some((token, _)) = self.tokens.get_key_value(term) {
+      matches.push((token.as_str(), 1.0));
+    }
+
+    if fuzzy {
+      // Typos are counted against the words as written, since stemming
+      // may take a misspelled word further away from the right one
+      let max_edits = fuzzy::max_edits(word);
+      let mut distances: BTreeMap<&str, usize> = BTreeMap::new();
+      for candidate in self.words.keys() {
+        match fuzzy::edit_distance(word, candidate, max_edits) {
+          Some(distance) if distance > 0 => {
+            if let Some((token, _)) = self.tokens.get_key_value(&stem(candidate)) {
+              if token != term {
+                let best = distances.entry(token.as_str()).or_insert(distance);
+                *best = (*best).min(distance);
+              }
+            }
+          }
+          _ => {}
+        }
+      }
+      matches.extend(distances.into_iter().map(|(token, distance)| (token, fuzzy::weight(distance))));
+    }
+
+    if let Some(prefix) = prefix {
+      // Completions weigh by how much of them has been typed
+      for (token, _) in prefix_range(&self.tokens, prefix) {
+        if token != term {
+          matches.push((token.as_str(), prefix.len() as f32 / token.len() as f32));
+        }
+      }
+    }
+    matches
+  }
+
+  /// Completions of a partially typed word, most common first
+  pub fn suggest(&self, prefix: &str, take: usize) -> Vec<&str> {
+    let mut completions: Vec<(&String, &u32)> = prefix_range(&self.words, prefix).collect();
+    completions.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    completions.into_iter().take(take).map(|(word, _)| word.as_str()).collect()
+  }
+
+  fn average_length(&self) -> f32 {
+    if self.lengths.is_empty() {
+      0.0
+    } else {
+      self.total_length as f32 / self.lengths.len() as f32
+    }
+  }
+
+  /// Removes all postings of a document
+  fn unindex(&mut self, id: u32) {
+    self.unindex_all(&std::iter::once(id).collect());
+  }
+
+  /// Removes all postings of several documents, visiting every posting
+  /// list they appear in once
+  fn unindex_all(&mut self, ids: &HashSet<u32>) {
+    let mut tokens: HashSet<String> = HashSet::new();
+    for id in ids.iter() {
+      tokens.extend(self.doc_tokens.remove(id).unwrap_or_default());
+      for word in self.doc_words.remove(id).unwrap_or_default() {
+        if let Some(count) = self.words.get_mut(&word) {
+          *count -= 1;
+          if *count == 0 {
+            self.words.remove(&word);
+          }
+        }
+      }
+      if let Some(len) = self.lengths.remove(id) {
+        self.total_length -= u64::from(len);
+      }
+    }
+
+    for token in tokens {
+      if let Some(postings) = self.tokens.get_mut(&token) {
+        postings.retain(|x| !ids.contains(&x.id));
+        if postings.is_empty() {
+          self.tokens.remove(&token);
+        }
+      }
+    }
+  }
+
+  /// Replaces the postings of a document with the tokens of `input`
+  fn index_text(&mut self, input: &T::Input, id: u32) {
+    self.unindex(id);
+
+    let mut positions: HashMap<String, BTreeMap<(u8, bool), Vec<u32>>> = HashMap::new();
+    let mut doc_words: HashSet<String> = HashSet::new();
+    let mut len = 0;
+    let mut offset = 0;
+    for text in T::text(input) {
+      let field = field_id::<T>(text.field).expect("text field missing from TEXT_FIELDS");
+      let words = positioned_words(text.value);
+      for (pos, word) in words.iter() {
+        let field_positions = positions.entry(stem(word)).or_insert_with(BTreeMap::new);
+        field_positions.entry((field, text.alias)).or_insert_with(Vec::new).push(offset + pos);
+        len += 1;
+      }
+      if let Some((last, _)) = words.last() {
+        offset += last + 1 + STRING_GAP;
+      }
+      doc_words.extend(words.into_iter().map(|(_, word)| word));
+    }
+
+    let mut doc_tokens = Vec::with_capacity(positions.len());
+    for (token, fields) in positions {
+      let fields = fields
+        .into_iter()
+        .map(|((field, alias), positions)| Occurrences { field, alias, positions })
+        .collect();
+      self.tokens.entry(token.clone()).or_insert_with(Vec::new).push(Posting { id, fields });
+      doc_tokens.push(token);
+    }
+    self.doc_tokens.insert(id, doc_tokens);
+    for word in doc_words.iter() {
+      *self.words.entry(word.clone()).or_insert(0) += 1;
+    }
+    self.doc_words.insert(id, doc_words.into_iter().collect());
+    self.lengths.insert(id, len);
+    self.total_length += u64::from(len);
+  }
+}
+
+/// Number of documents containing a token, only counting those that have
+/// it in `field` if given
+fn document_frequency(postings: &[Posting], field: Option<u8>) -> usize {
+  match field {
+    Some(_) => postings.iter().filter(|x| x.occurrences(field).next().is_some()).count(),
+    None => postings.len(),
+  }
+}
+
+/// The word and field a query term searches. A field this document type
+/// does not have, like in "re:zero", is part of the word.
+fn term_field<T: Document>(field: &Option<String>, word: &str) -> (String, Option<u8>) {
+  match field {
+    Some(name) => match field_id::<T>(name) {
+      Some(id) => (word.to_string(), Some(id)),
+      None => (format!("{}:{}", name, word), None),
+    },
+    None => (word.to_string(), None),
+  }
+}
+
+/// The only word of a query term. Short words are only searched while
+/// being typed.
+fn single_word(words: Vec<(u32, String)>, text: &str, prefix: bool) -> Option<String> {
+  match words.into_iter().next() {
+    Some((_, word)) => Some(word),
+    None => partial_word(text).filter(|_| prefix),
+  }
+}
+
+/// Text of a field with every word whose token is in `tokens` put between
+/// the tags of the query, `None` if the document has no such text
+fn highlight<T: Document>(doc: &T, name: &str, tokens: &[(Option<u8>, String)], query: &Query) -> Option<String> {
+  let text = match doc.field(name) {
+    Some(Field::Text(text)) => text,
+    _ => return None,
+  };
+  let field = field_id::<T>(name);
+
+  let mut highlighted = String::with_capacity(text.len());
+  let mut end = 0;
+  for span in spans(text) {
+    let token = stem(&span.word);
+    let matched = tokens
+      .iter()
+      .any(|(only, x)| *x == token && (only.is_none() || *only == field));
+    if matched {
+      escape_html(&text[end..span.start], &mut highlighted);
+      highlighted.push_str(&query.pre_tag);
+      escape_html(&text[span.start..span.end], &mut highlighted);
+      highlighted.push_str(&query.post_tag);
+      end = span.end;
+    }
+  }
+  escape_html(&text[end..], &mut highlighted);
+  Some(highlighted)
+}
+
+/// Appends `text` escaped for HTML, so only the highlight tags are markup
+fn escape_html(text: &str, out: &mut String) {
+  for c in text.chars() {
+    match c {
+      '&' => out.push_str("&amp;"),
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '"' => out.push_str("&quot;"),
+      '\'' => out.push_str("&#39;"),
+      _ => out.push(c),
+    }
+  }
+}
+
+/// Counts the values of a field among the hits that pass every filter but
+/// those on the field itself, so other values can still be selected
+fn facet<T: Document>(name: &str, hits: &[Hit<T>], query: &Query) -> Vec<FacetCount> {
+  let filters: Vec<&Filter> = query.filters.iter().filter(|x| x.field() != name).collect();
+
+  let mut counts: HashMap<FacetValue, usize> = HashMap::new();
+  for hit in hits.iter() {
+    if !filters.iter().all(|filter| filter.matches(hit.doc)) {
+      continue;
+    }
+    let values = match hit.doc.field(name) {
+      Some(Field::Flag(value)) => vec![FacetValue::Flag(value)],
+      Some(Field::Number(Some(value))) => vec![FacetValue::Number(value)],
+      Some(Field::Text(value)) => vec![FacetValue::Text(value.to_string())],
+      Some(Field::Ref(Some(value))) => vec![FacetValue::Text(value.to_string())],
+      Some(Field::Refs(values)) => values.iter().map(|x| FacetValue::Text(x.to_string())).collect(),
+      _ => Vec::new(),
+    };
+    for value in values {
+      *counts.entry(value).or_insert(0) += 1;
+    }
+  }
+
+  let mut counts: Vec<FacetCount> = counts
+    .into_iter()
+    .map(|(value, count)| FacetCount { value, count })
+    .collect();
+  counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+  counts.truncate(query.facet_take);
+  counts
+}
+
+/// Index of a field name in `Document::TEXT_FIELDS`
+fn field_id<T: Document>(name: &str) -> Option<u8> {
+  T::TEXT_FIELDS.iter().position(|x| *x == name).map(|x| x as u8)
+}
+
+/// Entries of an ordered map whose key starts with `prefix`
+fn prefix_range<'a: 'b, 'b, V>(
+  map: &'a BTreeMap<String, V>,
+  prefix: &'b str,
+) -> impl Iterator<Item = (&'a String, &'a V)> + 'b {
+  map
+    .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+    .take_while(move |(key, _)| key.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::document::Aliasable;
+  use crate::scene::{InputScene, SceneIndex, StoredScene};
+
+  fn aliasable(id: &str, name: &str, aliases: &[&str]) -> Aliasable {
+    Aliasable {
+      id: id.to_string(),
+      name: name.to_string(),
+      aliases: Some(aliases.iter().map(|x| x.to_string()).collect()),
+    }
+  }
+
+  fn scene(id: &str, name: &str) -> InputScene {
+    InputScene {
+      id: id.to_string(),
+      name: name.to_string(),
+      added_on: 0,
+      release_date: None,
+      bookmark: None,
+      favorite: false,
+      rating: None,
+      actors: Vec::new(),
+      labels: Vec::new(),
+      num_watches: 0,
+      duration: None,
+      size: None,
+      studio: None,
+      studio_name: None,
+      resolution: None,
+    }
+  }
+
+  /// Fields and alias flags a token occurs in for a document
+  fn occurrences(index: &SceneIndex, token: &str, id: &str) -> Vec<(&'static str, bool, Vec<u32>)> {
+    let internal_id = index.id_map[id];
+    index.tokens[token]
+      .iter()
+      .filter(|x| x.id == internal_id)
+      .flat_map(|x| x.fields.iter())
+      .map(|x| (StoredScene::TEXT_FIELDS[x.field as usize], x.alias, x.positions.clone()))
+      .collect()
+  }
+
+  fn ids<'a>(result: &SearchResult<'a, StoredScene>) -> Vec<&'a str> {
+    result.items.iter().map(|x| x.doc.id()).collect()
+  }
+
+  #[test]
+  fn insert_indexes_tokens_by_field() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Blonde beach day");
+    input.actors = vec![aliasable("a1", "Anna Bell", &["Annie"])];
+    input.labels = vec![aliasable("l1", "Blondes", &[])];
+    index.insert(&input);
+
+    assert_eq!(index.len(), 1);
+    assert_eq!(occurrences(&index, "blond", "sc_1"), vec![("name", false, vec![0]), ("label", false, vec![306])]);
+    assert_eq!(occurrences(&index, "anni", "sc_1"), vec![("actor", true, vec![205])]);
+    assert_eq!(index.lengths[&index.id_map["sc_1"]], 7);
+    assert_eq!(index.words["blonde"], 1);
+  }
+
+  #[test]
+  fn insert_with_a_known_id_replaces_the_document() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_1", "Pool party"));
+
+    assert_eq!(index.len(), 1);
+    assert!(!index.tokens.contains_key("beach"));
+    assert!(index.tokens.contains_key("pool"));
+    assert_eq!(index.total_length, 2);
+  }
+
+  #[test]
+  fn update_replaces_postings() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "Another day"));
+
+    assert!(index.update("sc_1", &scene("sc_1", "Pool day")));
+    assert!(!index.tokens.contains_key("blond"));
+    assert!(!index.words.contains_key("beach"));
+    assert_eq!(index.tokens["day"].len(), 2);
+    assert_eq!(occurrences(&index, "pool", "sc_1"), vec![("name", false, vec![0])]);
+    assert_eq!(index.total_length, 4);
+
+    assert!(!index.update("unknown", &scene("unknown", "Pool day")));
+    assert!(!index.contains("unknown"));
+  }
+
+  #[test]
+  fn update_rejects_a_different_input_id() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "Another day"));
+
+    assert!(!index.update("sc_1", &scene("sc_2", "Pool day")));
+    assert!(!index.update("sc_1", &scene("sc_3", "Pool day")));
+    assert_eq!(index.len(), 2);
+    assert_eq!(ids(&index.search(&Query::new("beach").unwrap())), vec!["sc_1"]);
+    assert_eq!(index.search(&Query::new("pool").unwrap()).num_hits, 0);
+  }
+
+  #[test]
+  fn remove_drops_postings_and_words() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "Another day"));
+
+    assert!(index.remove("sc_1"));
+    assert!(!index.remove("sc_1"));
+    assert_eq!(index.len(), 1);
+    assert!(!index.tokens.contains_key("beach"));
+    assert_eq!(index.tokens["day"].len(), 1);
+    assert_eq!(index.words["day"], 1);
+    assert_eq!(index.total_length, 2);
+    assert!(!index.doc_tokens.contains_key(&0));
+  }
+
+  #[test]
+  fn remove_many_matches_removing_one_by_one() {
+    let mut index = SceneIndex::new();
+    for i in 0..10 {
+      index.insert(&scene(&format!("sc_{}", i), &format!("Beach day {}", i * 111)));
+    }
+
+    let ids = vec!["sc_1".to_string(), "sc_3".to_string(), "unknown".to_string(), "sc_3".to_string()];
+    assert_eq!(index.remove_many(&ids), 2);
+    assert_eq!(index.len(), 8);
+    assert_eq!(index.tokens["beach"].len(), 8);
+    assert!(!index.tokens.contains_key("111"));
+    assert!(!index.tokens.contains_key("333"));
+    assert_eq!(index.words["day"], 8);
+    assert_eq!(index.total_length, 8 * 3 - 1);
+  }
+
+  #[test]
+  fn search_ranks_required_and_excluded_words() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "Blonde pool day"));
+    index.insert(&scene("sc_3", "Beach party"));
+
+    let result = index.search(&Query::new("+blonde -pool").unwrap());
+    assert_eq!(ids(&result), vec!["sc_1"]);
+
+    let result = index.search(&Query::new("blondes beach").unwrap());
+    assert_eq!(ids(&result)[0], "sc_1");
+    assert_eq!(result.num_hits, 3);
+  }
+
+  #[test]
+  fn fuzzy_matches_typos_of_the_unstemmed_word() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "Blond pool day"));
+
+    assert_eq!(index.search(&Query::new("blnde").unwrap()).num_hits, 0);
+    let result = index.search(&Query::new("blnde").unwrap().fuzzy(Some(true)));
+    assert_eq!(result.num_hits, 2);
+
+    // A typo counts less than the exact word
+    let exact = index.search(&Query::new("beach").unwrap());
+    let typo = index.search(&Query::new("bech").unwrap().fuzzy(Some(true)));
+    assert_eq!(ids(&typo), vec!["sc_1"]);
+    assert!(typo.items[0].score < exact.items[0].score);
+  }
+
+  #[test]
+  fn text_without_searchable_words_matches_nothing() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+    index.insert(&scene("sc_2", "TV night"));
+
+    for text in &["tv", "4k", "!!!", "+tv", "-tv", "(tv OR 4k)"] {
+      assert_eq!(index.search(&Query::new(text).unwrap()).num_hits, 0, "{}", text);
+    }
+    // Short words next to longer ones are ignored
+    assert_eq!(ids(&index.search(&Query::new("tv beach").unwrap())), vec!["sc_1"]);
+    assert_eq!(index.search(&Query::new("").unwrap()).num_hits, 2);
+  }
+
+  /// Highlighted name of the first hit
+  fn highlighted(index: &SceneIndex, query: Query) -> String {
+    let result = index.search(&query.highlight(Some("name".into()), None, None));
+    result.items[0].highlights["name"].clone()
+  }
+
+  #[test]
+  fn highlight_marks_stemmed_words() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Blonde beach day"));
+
+    assert_eq!(highlighted(&index, Query::new("blondes").unwrap()), "<em>Blonde</em> beach day");
+    assert_eq!(
+      highlighted(&index, Query::new("beach blonde").unwrap()),
+      "<em>Blonde</em> <em>beach</em> day"
+    );
+  }
+
+  #[test]
+  fn highlight_skips_other_fields_and_excluded_words() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Blonde beach pool day");
+    input.actors = vec![aliasable("a1", "Blonde Bell", &[])];
+    index.insert(&input);
+
+    assert_eq!(highlighted(&index, Query::new("actor:blonde").unwrap()), "Blonde beach pool day");
+    assert_eq!(highlighted(&index, Query::new("name:blonde").unwrap()), "<em>Blonde</em> beach pool day");
+    // The name contains the excluded word, only the actor may not
+    assert_eq!(
+      highlighted(&index, Query::new("beach -actor:pool").unwrap()),
+      "Blonde <em>beach</em> pool day"
+    );
+  }
+
+  #[test]
+  fn highlight_uses_custom_tags_and_escapes_text() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Tom & Jerry's <beach> \"day\""));
+
+    let query = Query::new("beach").unwrap().highlight(Some("name".into()), Some("<b>".into()), Some("</b>".into()));
+    let result = index.search(&query);
+    assert_eq!(
+      result.items[0].highlights["name"],
+      "Tom &amp; Jerry&#39;s &lt;<b>beach</b>&gt; &quot;day&quot;"
+    );
+  }
+
+  #[test]
+  fn prefix_completes_only_the_last_word() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Beach day"));
+    index.insert(&scene("sc_2", "Beachwear party"));
+    index.insert(&scene("sc_3", "Bea and Pool"));
+    index.insert(&scene("sc_4", "Pool party"));
+
+    let search = |text: &str, prefix: bool| {
+      let query = Query::new(text).unwrap().prefix(Some(prefix));
+      ids(&index.search(&query)).iter().map(|x| x.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(search("bea", false), vec!["sc_3"]);
+    // The word as typed ranks first, then the shortest completion
+    assert_eq!(search("bea", true), vec!["sc_3", "sc_1", "sc_2"]);
+    assert_eq!(search("+beachw", true), vec!["sc_2"]);
+    assert_eq!(search("bea +pool", true), vec!["sc_3", "sc_4"]);
+    assert_eq!(search("+pool bea", true)[0], "sc_3");
+    assert_eq!(search("+pool bea", true).len(), 2);
+  }
+
+  #[test]
+  fn suggest_orders_by_frequency_then_word() {
+    let mut index = SceneIndex::new();
+    index.insert(&scene("sc_1", "Beach day"));
+    index.insert(&scene("sc_2", "Beachwear beach party"));
+    index.insert(&scene("sc_3", "Beaches and bears"));
+    index.insert(&scene("sc_4", "Pool party bears"));
+
+    assert_eq!(index.suggest("bea", 10), vec!["beach", "bears", "beaches", "beachwear"]);
+    assert_eq!(index.suggest("bea", 2), vec!["beach", "bears"]);
+    assert_eq!(index.suggest("par", 10), vec!["party"]);
+    assert!(index.suggest("x", 10).is_empty());
+  }
+
+  #[test]
+  fn phrases_do_not_cross_strings() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Day");
+    input.actors = vec![aliasable("a1", "Anna", &[]), aliasable("a2", "Bell", &[])];
+    index.insert(&input);
+    index.insert(&scene("sc_2", "Anna Bell"));
+
+    let result = index.search(&Query::new("\"anna bell\"").unwrap());
+    assert_eq!(ids(&result), vec!["sc_2"]);
+  }
+
+  /// Matched terms of the only hit as (term, token, field, alias), sorted
+  fn term_matches(index: &SceneIndex, query: Query) -> Vec<(String, String, &'static str, bool)> {
+    let result = index.search(&query.with_scores(Some(true)));
+    assert_eq!(result.items.len(), 1);
+    let mut matches: Vec<_> = result.items[0]
+      .matches
+      .iter()
+      .map(|x| (x.term.clone(), x.token.clone(), x.field, x.alias))
+      .collect();
+    matches.sort();
+    matches
+  }
+
+  #[test]
+  fn matches_report_field_and_alias() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Blonde beach day");
+    input.actors = vec![aliasable("a1", "Anna Bell", &["Annie"])];
+    input.labels = vec![aliasable("l1", "Blondes", &[])];
+    index.insert(&input);
+    index.insert(&scene("sc_2", "Pool party"));
+
+    let owned = |term: &str, token: &str, field, alias| (term.to_string(), token.to_string(), field, alias);
+    assert_eq!(
+      term_matches(&index, Query::new("blonde annie anna").unwrap()),
+      vec![
+        owned("anna", "anna", "actor", false),
+        owned("annie", "anni", "actor", true),
+        owned("blonde", "blond", "label", false),
+        owned("blonde", "blond", "name", false),
+      ]
+    );
+    assert_eq!(
+      term_matches(&index, Query::new("label:blonde").unwrap()),
+      vec![owned("blonde", "blond", "label", false)]
+    );
+    assert_eq!(
+      term_matches(&index, Query::new("bech").unwrap().fuzzy(Some(true))),
+      vec![owned("bech", "beach", "name", false)]
+    );
+  }
+
+  #[test]
+  fn query_boosts_override_index_boosts() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Pool party");
+    input.labels = vec![aliasable("l1", "Beach", &[])];
+    index.insert(&input);
+    index.insert(&scene("sc_2", "Beach party day"));
+
+    let search = |index: &SceneIndex, boost: Option<&str>| {
+      let query = Query::new("beach").unwrap().boost(boost.map(String::from), StoredScene::TEXT_FIELDS).unwrap();
+      ids(&index.search(&query)).iter().map(|x| x.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(search(&index, None), vec!["sc_2", "sc_1"]);
+
+    let mut boosts = Boosts::default();
+    boosts.set("label", 10.0);
+    index.set_boosts(boosts);
+    assert_eq!(search(&index, None), vec!["sc_1", "sc_2"]);
+    assert_eq!(search(&index, Some("name:20")), vec!["sc_2", "sc_1"]);
+    // Fields left out of the query keep the weights of the index
+    assert_eq!(search(&index, Some("actor:20")), vec!["sc_1", "sc_2"]);
+
+    let ratio = |boost: &str| {
+      let query = Query::new("beach").unwrap().boost(Some(boost.to_string()), StoredScene::TEXT_FIELDS).unwrap();
+      let result = index.search(&query);
+      let score = |id| result.items.iter().find(|x| x.doc.id() == id).unwrap().score;
+      score("sc_1") / score("sc_2")
+    };
+    assert!((ratio("name:4,label:4") - 1.0).abs() < 1e-6);
+    assert!(ratio("name:2,label:4") > 1.0);
+  }
+
+  #[test]
+  fn explanation_matches_the_search_score() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Blonde beach day");
+    input.actors = vec![aliasable("a1", "Anna Bell", &["Annie"])];
+    index.insert(&input);
+    index.insert(&scene("sc_2", "Another day"));
+
+    let query = Query::new("annie \"beach day\"").unwrap().explain(Some("sc_1".into()));
+    let result = index.search(&query);
+    let explanation = result.explanation.unwrap();
+    assert_eq!(explanation.position, Some(0));
+    assert_eq!(explanation.score, result.items[0].score);
+    assert_eq!(explanation.relevance.unwrap().value, result.items[0].score);
+  }
+
+  /// Reason the explained document does not match the query text
+  fn reason(index: &SceneIndex, text: &str, id: &str) -> Option<String> {
+    let query = Query::new(text).unwrap().explain(Some(id.into()));
+    let explanation = index.search(&query).explanation.unwrap();
+    assert_eq!(explanation.matched, explanation.reason.is_none(), "{}", text);
+    explanation.reason
+  }
+
+  #[test]
+  fn explanation_names_the_clause_ruling_a_document_out() {
+    let mut index = SceneIndex::new();
+    let mut input = scene("sc_1", "Blonde beach day");
+    input.actors = vec![aliasable("a1", "Anna Bell", &[])];
+    index.insert(&input);
+    index.insert(&scene("sc_2", "Pool party"));
+
+    assert_eq!(reason(&index, "beach -actor:anna", "sc_1").unwrap(), "has the excluded clause -actor:anna");
+    assert_eq!(reason(&index, "day +pool", "sc_1").unwrap(), "lacks the required clause +pool");
+    assert_eq!(
+      reason(&index, "+(pool OR lake) beach", "sc_1").unwrap(),
+      "lacks the required clause +(pool OR lake)"
+    );
+    assert_eq!(reason(&index, "-\"beach day\"", "sc_1").unwrap(), "has the excluded clause -\"beach day\"");
+    assert_eq!(reason(&index, "pool party", "sc_1").unwrap(), "matches none of the clauses");
+    assert_eq!(reason(&index, "pool OR lake", "sc_1").unwrap(), "matches none of the alternatives");
+    assert_eq!(reason(&index, "tv", "sc_1").unwrap(), "the query has no words long enough to search");
+    assert!(reason(&index, "beach -pool", "sc_1").is_none());
+    assert!(reason(&index, "", "sc_1").is_none());
+  }
+
+  /// Ids of every page of a search, following the cursors
+  fn all_pages(index: &SceneIndex, sort: &str, take: usize) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut after = None;
+    loop {
+      let query = Query::new("")
+        .unwrap()
+        .page(None, Some(take))
+        .unwrap()
+        .sort_keys::<StoredScene>(Some(sort.to_string()))
+        .unwrap()
+        .after(after)
+        .unwrap();
+      let result = index.search(&query);
+      assert!(result.items.len() <= take);
+      ids.extend(result.items.iter().map(|x| x.doc.id().to_string()));
+      match result.next {
+        Some(next) => after = Some(next),
+        None => return ids,
+      }
+    }
+  }
+
+  fn rated_scenes() -> SceneIndex {
+    let mut index = SceneIndex::new();
+    for i in 0..25 {
+      let mut input = scene(&format!("sc_{:02}", i), "Beach day");
+      input.rating = if i % 4 == 0 { None } else { Some((i % 3) as u8) };
+      index.insert(&input);
+    }
+    index
+  }
+
+  fn labelled(id: &str, labels: &[&str], rating: Option<u8>) -> InputScene {
+    let mut input = scene(id, "Beach day");
+    input.labels = labels.iter().map(|x| aliasable(x, x, &[])).collect();
+    input.rating = rating;
+    input
+  }
+
+  #[test]
+  fn facets_ignore_only_the_filters_on_their_own_field() {
+    let mut index = SceneIndex::new();
+    index.insert(&labelled("sc_1", &["l1", "l2"], Some(3)));
+    index.insert(&labelled("sc_2", &["l2"], Some(3)));
+    index.insert(&labelled("sc_3", &["l1"], Some(1)));
+    index.insert(&labelled("sc_4", &["l1", "l3"], None));
+
+    let query = Query::new("")
+      .unwrap()
+      .all("labels", Some("l1".into()))
+      .min("rating", Some(2))
+      .facets(Some("labels,rating".into()), None);
+    let result = index.search(&query);
+    assert_eq!(ids(&result), vec!["sc_1"]);
+
+    // Rated scenes with any label, and scenes labelled l1 with any rating
+    let facets = serde_json::to_value(&result.facets).unwrap();
+    assert_eq!(
+      facets,
+      serde_json::json!({
+        "labels": [{ "value": "l2", "count": 2 }, { "value": "l1", "count": 1 }],
+        "rating": [{ "value": 1, "count": 1 }, { "value": 3, "count": 1 }],
+      })
+    );
+  }
+
+  #[test]
+  fn facets_keep_the_most_common_values() {
+    let mut index = SceneIndex::new();
+    index.insert(&labelled("sc_1", &["l1", "l2"], None));
+    index.insert(&labelled("sc_2", &["l2", "l3"], None));
+    index.insert(&labelled("sc_3", &["l2", "l3"], None));
+
+    let query = Query::new("").unwrap().facets(Some("labels".into()), Some(2));
+    let facets = serde_json::to_value(index.search(&query).facets).unwrap();
+    assert_eq!(
+      facets,
+      serde_json::json!({ "labels": [{ "value": "l2", "count": 3 }, { "value": "l3", "count": 2 }] })
+    );
+  }
+
+  #[test]
+  fn ranges_never_match_missing_values() {
+    let index = rated_scenes();
+    let unrated: Vec<String> = (0..25).step_by(4).map(|i| format!("sc_{:02}", i)).collect();
+    let search = |query: Query| ids(&index.search(&query)).iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    for query in vec![
+      Query::new("").unwrap().min("rating", Some(0)),
+      Query::new("").unwrap().max("rating", Some(5)),
+      Query::new("").unwrap().range("rating", Some(-1), Some(0)),
+    ] {
+      let hits = search(query);
+      assert!(!hits.is_empty());
+      assert!(hits.iter().all(|x| !unrated.contains(x)), "{:?}", hits);
+    }
+    // Unrated scenes are neither above nor below any value
+    assert_eq!(search(Query::new("").unwrap().min("rating", Some(0))).len(), 18);
+    assert_eq!(search(Query::new("").unwrap().max("rating", Some(i64::MAX))).len(), 18);
+    assert_eq!(search(Query::new("").unwrap().max("rating", Some(0))).len(), 6);
+  }
+
+  #[test]
+  fn every_listed_field_can_be_looked_up() {
+    let doc = StoredScene::from_input(&scene("sc_1", "Beach day"));
+    for name in StoredScene::FIELDS {
+      assert!(doc.field(name).is_some(), "{}", name);
+    }
+  }
+
+  #[test]
+  fn cursor_pages_cover_every_hit_once() {
+    let index = rated_scenes();
+    for sort in &["rating:desc", "rating:asc:nulls_first", "name:asc,rating:desc"] {
+      let all: Vec<String> = {
+        let query = Query::new("").unwrap().sort_keys::<StoredScene>(Some(sort.to_string())).unwrap();
+        index.search(&query).items.iter().map(|x| x.doc.id().to_string()).collect()
+      };
+      for take in &[1, 7, 25, 30] {
+        assert_eq!(all_pages(&index, sort, *take), all, "sort {} take {}", sort, take);
+      }
+    }
+  }
+
+  #[test]
+  fn cursor_survives_inserts_before_it() {
+    let mut index = rated_scenes();
+    let query = Query::new("")
+      .unwrap()
+      .page(None, Some(10))
+      .unwrap()
+      .sort_keys::<StoredScene>(Some("rating:desc".into()))
+      .unwrap();
+    let first = index.search(&query);
+    let first_ids: Vec<String> = first.items.iter().map(|x| x.doc.id().to_string()).collect();
+    let next = first.next;
+
+    // Sorts before the cursor, so it must not show up on later pages
+    let mut input = scene("sc_new", "Beach day");
+    input.rating = Some(5);
+    index.insert(&input);
+
+    let query = Query::new("")
+      .unwrap()
+      .sort_keys::<StoredScene>(Some("rating:desc".into()))
+      .unwrap()
+      .after(next)
+      .unwrap();
+    let rest: Vec<String> = index.search(&query).items.iter().map(|x| x.doc.id().to_string()).collect();
+    assert_eq!(first_ids.len() + rest.len(), 25);
+    assert!(!rest.contains(&"sc_new".to_string()));
+    assert!(rest.iter().all(|x| !first_ids.contains(x)));
+  }
+}
@@ -0,0 +1,25 @@
+//! Simple search engine used for porn-vault.
+//!
+//! Each entity type implements `Document` and is kept in its own `Index`.
+//! The `twigs` binary exposes these indexes over HTTP.
+
+#[macro_use]
+extern crate serde_derive;
+
+pub mod config;
+pub mod document;
+pub mod fuzzy;
+pub mod index;
+pub mod parser;
+pub mod query;
+pub mod scoring;
+pub mod snapshot;
+pub mod store;
+pub mod tokenizer;
+pub mod wal;
+
+pub mod actor;
+pub mod image;
+pub mod movie;
+pub mod scene;
+pub mod studio;
@@ -5,12 +5,8 @@
 extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
-#[macro_use]
-extern crate serde_derive;
 
-// mod actor;
-mod scene;
-mod image;
+mod routes;
 
 use rocket::config::{Config, Environment, Limits};
 use std::vec::Vec;
@@ -32,12 +28,21 @@ fn main() {
     .limits(limits)
     .unwrap();
 
+  routes::scene::load();
+  routes::image::load();
+  routes::actor::load();
+  routes::studio::load();
+  routes::movie::load();
+
   let app = rocket::custom(config);
 
   app
     .mount("/", routes![index])
-    .mount("/scene", scene::get_routes())
-    .mount("/image", image::get_routes())
-  //.mount("/actor", actor::get_routes())
+    .mount("/scene", routes::scene::get_routes())
+    .mount("/image", routes::image::get_routes())
+    .mount("/actor", routes::actor::get_routes())
+    .mount("/studio", routes::studio::get_routes())
+    .mount("/movie", routes::movie::get_routes())
+    .mount("/search", routes::search::get_routes())
     .launch();
 }
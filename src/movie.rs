@@ -0,0 +1,115 @@
+use crate::document::{aliasable_ids, aliasable_text, Aliasable, Document, Field, Text};
+use crate::index::Index;
+use std::vec::Vec;
+
+pub type MovieIndex = Index<StoredMovie>;
+
+/// A movie, `actors` and `labels` are those of all its scenes and
+/// `duration` is their total
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputMovie {
+  pub id: String,
+  pub name: String,
+  pub added_on: i64,
+  pub release_date: Option<i64>,
+  pub bookmark: Option<i64>,
+  pub favorite: bool,
+  pub rating: Option<u8>,
+  pub actors: Vec<Aliasable>,
+  pub labels: Vec<Aliasable>,
+  pub duration: Option<u32>,
+  pub num_scenes: u32,
+  pub studio: Option<String>,
+  pub studio_name: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredMovie {
+  pub id: String,
+  pub name: String,
+  pub added_on: i64,
+  pub release_date: Option<i64>,
+  pub bookmark: Option<i64>,
+  pub favorite: bool,
+  pub rating: Option<u8>,
+  pub studio: Option<String>,
+  pub actors: Vec<String>,
+  pub labels: Vec<String>,
+  pub duration: Option<u32>,
+  pub num_scenes: u32,
+}
+
+impl Document for StoredMovie {
+  type Input = InputMovie;
+
+  const TEXT_FIELDS: &'static [&'static str] = &["name", "actor", "label", "studio"];
+  const FIELDS: &'static [&'static str] = &[
+    "name",
+    "alpha",
+    "added_on",
+    "addedOn",
+    "release_date",
+    "date",
+    "bookmark",
+    "favorite",
+    "rating",
+    "studio",
+    "actors",
+    "labels",
+    "duration",
+    "num_scenes",
+    "numScenes",
+  ];
+
+  fn from_input(input: &InputMovie) -> StoredMovie {
+    StoredMovie {
+      id: input.id.clone(),
+      name: input.name.clone(),
+      added_on: input.added_on,
+      release_date: input.release_date,
+      bookmark: input.bookmark,
+      favorite: input.favorite,
+      rating: input.rating,
+      studio: input.studio.clone(),
+      actors: aliasable_ids(&input.actors),
+      labels: aliasable_ids(&input.labels),
+      duration: input.duration,
+      num_scenes: input.num_scenes,
+    }
+  }
+
+  fn input_id(input: &InputMovie) -> &str {
+    &input.id
+  }
+
+  fn id(&self) -> &str {
+    &self.id
+  }
+
+  fn text(input: &InputMovie) -> Vec<Text> {
+    let mut text = vec![Text::new("name", &input.name)];
+    if let Some(studio_name) = &input.studio_name {
+      text.push(Text::new("studio", studio_name));
+    }
+    text.extend(aliasable_text("actor", &input.actors));
+    text.extend(aliasable_text("label", &input.labels));
+    text
+  }
+
+  fn field(&self, name: &str) -> Option<Field> {
+    match name {
+      "name" | "alpha" => Some(Field::Text(&self.name)),
+      "added_on" | "addedOn" => Some(Field::Number(Some(self.added_on))),
+      "release_date" | "date" => Some(Field::Number(self.release_date)),
+      "bookmark" => Some(Field::Number(self.bookmark)),
+      "favorite" => Some(Field::Flag(self.favorite)),
+      "rating" => Some(Field::Number(self.rating.map(i64::from))),
+      "studio" => Some(Field::Ref(self.studio.as_deref())),
+      "actors" => Some(Field::Refs(self.actors.iter().map(|x| x.as_str()).collect())),
+      "labels" => Some(Field::Refs(self.labels.iter().map(|x| x.as_str()).collect())),
+      "duration" => Some(Field::Number(self.duration.map(i64::from))),
+      "num_scenes" | "numScenes" => Some(Field::Number(Some(i64::from(self.num_scenes)))),
+      _ => None,
+    }
+  }
+}
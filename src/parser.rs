@@ -0,0 +1,514 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::vec::Vec;
+
+/// Deepest nesting of parentheses, parsing and scoring recurse once per level
+const MAX_DEPTH: usize = 32;
+
+/// How a clause takes part in the group it belongs to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Occur {
+  /// Counts towards relevance, at least one has to match if nothing is required
+  Should,
+  /// Has to match, prefixed with `+`
+  Must,
+  /// Must not match, prefixed with `-`
+  MustNot,
+}
+
+#[derive(Debug)]
+pub struct Clause {
+  pub occur: Occur,
+  pub expr: Expr,
+}
+
+/// Parsed query text
+#[derive(Debug)]
+pub enum Expr {
+  /// Unquoted word, `partial` if it ends the query text and may still be
+  /// being typed. Only searches `field` if given as `field:word`.
+  Term {
+    field: Option<String>,
+    word: String,
+    partial: bool,
+  },
+  /// Quoted words that have to appear next to each other, only in `field`
+  /// if given as `field:"some words"`
+  Phrase { field: Option<String>, phrase: String },
+  /// Clauses separated by whitespace
+  Group(Vec<Clause>),
+  /// Groups separated by `OR`, any of which may match
+  Or(Vec<Expr>),
+}
+
+/// Query syntax of an expression
+impl fmt::Display for Expr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Expr::Term { field: Some(field), word, .. } => write!(f, "{}:{}", field, word),
+      Expr::Term { word, .. } => write!(f, "{}", word),
+      Expr::Phrase { field: Some(field), phrase } => write!(f, "{}:\"{}\"", field, phrase),
+      Expr::Phrase { phrase, .. } => write!(f, "\"{}\"", phrase),
+      Expr::Group(clauses) => {
+        let clauses: Vec<String> = clauses.iter().map(|x| x.to_string()).collect();
+        write!(f, "{}", clauses.join(" "))
+      }
+      Expr::Or(exprs) => {
+        let exprs: Vec<String> = exprs.iter().map(|x| x.to_string()).collect();
+        write!(f, "{}", exprs.join(" OR "))
+      }
+    }
+  }
+}
+
+impl fmt::Display for Clause {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let occur = match self.occur {
+      Occur::Should => "",
+      Occur::Must => "+",
+      Occur::MustNot => "-",
+    };
+    match self.expr {
+      // Nested groups only come from parentheses
+      Expr::Group(_) | Expr::Or(_) => write!(f, "{}({})", occur, self.expr),
+      _ => write!(f, "{}{}", occur, self.expr),
+    }
+  }
+}
+
+/// Invalid query syntax, `position` is the byte offset of the problem
+#[derive(Debug, Serialize)]
+pub struct ParseError {
+  pub message: String,
+  pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at position {}", self.message, self.position)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+  Word(String),
+  Phrase(Option<String>, String),
+  Required,
+  Excluded,
+  Or,
+  Open,
+  Close,
+}
+
+/// Parses query text like `+blonde -(beach OR pool) actor:"anna bell"`.
+/// Returns `None` for blank text.
+pub fn parse(text: &str) -> Result<Option<Expr>, ParseError> {
+  let tokens = lex(text)?;
+  if tokens.is_empty() {
+    return Ok(None);
+  }
+
+  let mut parser = Parser {
+    tokens,
+    pos: 0,
+    end: text.len(),
+  };
+  let expr = parser.or(0)?;
+  match parser.tokens.get(parser.pos) {
+    Some((position, _)) => Err(error("Unmatched ')'", *position)),
+    None => Ok(Some(expr)),
+  }
+}
+
+fn error(message: &str, position: usize) -> ParseError {
+  ParseError {
+    message: message.to_string(),
+    position,
+  }
+}
+
+/// Whether `name` can be the field of a `field:value` term
+fn is_field_name(name: &str) -> bool {
+  !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+/// Splits `field:word` into its parts
+fn split_field(word: &str) -> (Option<String>, String) {
+  match word.find(':') {
+    Some(i) if is_field_name(&word[..i]) && i + 1 < word.len() => {
+      (Some(word[..i].to_string()), word[i + 1..].to_string())
+    }
+    _ => (None, word.to_string()),
+  }
+}
+
+fn lex_phrase(text: &str, chars: &mut Peekable<CharIndices>) -> Result<String, ParseError> {
+  let (start, _) = chars.next().unwrap();
+  let phrase = take_while(text, chars, |c| c != '"');
+  if chars.next().is_none() {
+    return Err(error("Unterminated quote", start));
+  }
+  Ok(phrase.to_string())
+}
+
+/// Whether `c` ends an unquoted word
+fn is_delimiter(c: char) -> bool {
+  c.is_whitespace() || c == '(' || c == ')' || c == '"'
+}
+
+fn lex(text: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+  let mut tokens = Vec::new();
+  let mut chars = text.char_indices().peekable();
+
+  while let Some(&(start, c)) = chars.peek() {
+    if c.is_whitespace() {
+      chars.next();
+      continue;
+    }
+
+    let token = match c {
+      '(' => {
+        chars.next();
+        Token::Open
+      }
+      ')' => {
+        chars.next();
+        Token::Close
+      }
+      '"' => Token::Phrase(None, lex_phrase(text, &mut chars)?),
+      _ => {
+        chars.next();
+        // `+` and `-` are operators only when directly followed by a term,
+        // so "beach - day" still searches for both words
+        let operand = match chars.peek() {
+          Some(&(_, next)) => !next.is_whitespace() && next != ')',
+          None => false,
+        };
+        match c {
+          '+' if operand => Token::Required,
+          '-' if operand => Token::Excluded,
+          _ => {
+            let rest = take_while(text, &mut chars, |c| !is_delimiter(c));
+            let word = &text[start..start + c.len_utf8() + rest.len()];
+            let field = word.strip_suffix(':').filter(|x| is_field_name(x));
+            if word == "OR" {
+              Token::Or
+            } else if let (Some(field), Some((_, '"'))) = (field, chars.peek()) {
+              Token::Phrase(Some(field.to_string()), lex_phrase(text, &mut chars)?)
+            } else {
+              Token::Word(word.to_string())
+            }
+          }
+        }
+      }
+    };
+    tokens.push((start, token));
+  }
+  Ok(tokens)
+}
+
+/// Consumes characters while `predicate` holds, returning them as a slice of `text`
+fn take_while<'a>(text: &'a str, chars: &mut Peekable<CharIndices>, predicate: fn(char) -> bool) -> &'a str {
+  let start = match chars.peek() {
+    Some(&(i, _)) => i,
+    None => return "",
+  };
+  let mut end = start;
+  while let Some(&(i, c)) = chars.peek() {
+    if !predicate(c) {
+      break;
+    }
+    end = i + c.len_utf8();
+    chars.next();
+  }
+  &text[start..end]
+}
+
+struct Parser {
+  tokens: Vec<(usize, Token)>,
+  pos: usize,
+  /// Length of the query text
+  end: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos).map(|(_, token)| token)
+  }
+
+  /// Position of the next token, or the end of the text
+  fn position(&self) -> usize {
+    self.tokens.get(self.pos).map(|(i, _)| *i).unwrap_or(self.end)
+  }
+
+  fn or(&mut self, depth: usize) -> Result<Expr, ParseError> {
+    let mut groups = vec![self.group(depth)?];
+    while self.peek() == Some(&Token::Or) {
+      self.pos += 1;
+      groups.push(self.group(depth)?);
+    }
+
+    if groups.len() == 1 {
+      Ok(groups.pop().unwrap())
+    } else {
+      Ok(Expr::Or(groups))
+    }
+  }
+
+  fn group(&mut self, depth: usize) -> Result<Expr, ParseError> {
+    let mut clauses = Vec::new();
+    while let Some(token) = self.peek() {
+      if *token == Token::Or || *token == Token::Close {
+        break;
+      }
+      clauses.push(self.clause(depth)?);
+    }
+
+    if clauses.is_empty() {
+      let after_open = self.pos > 0 && self.tokens[self.pos - 1].1 == Token::Open;
+      let message = match self.peek() {
+        Some(Token::Close) if depth == 0 => "Unmatched ')'",
+        Some(Token::Close) if after_open => "Empty parentheses",
+        _ => "Expected a term",
+      };
+      return Err(error(message, self.position()));
+    }
+    Ok(Expr::Group(clauses))
+  }
+
+  fn clause(&mut self, depth: usize) -> Result<Clause, ParseError> {
+    let occur = match self.peek() {
+      Some(Token::Required) => Occur::Must,
+      Some(Token::Excluded) => Occur::MustNot,
+      _ => Occur::Should,
+    };
+    if occur != Occur::Should {
+      self.pos += 1;
+    }
+
+    let position = self.position();
+    let expr = match self.tokens.get_mut(self.pos).map(|(_, token)| token) {
+      Some(Token::Word(word)) => {
+        let partial = position + word.len() == self.end;
+        let (field, word) = split_field(word);
+        Expr::Term { field, word, partial }
+      }
+      Some(Token::Phrase(field, phrase)) => Expr::Phrase {
+        field: field.take(),
+        phrase: std::mem::take(phrase),
+      },
+      Some(Token::Open) if depth >= MAX_DEPTH => {
+        return Err(error("Too many nested parentheses", position));
+      }
+      Some(Token::Open) => {
+        self.pos += 1;
+        let expr = self.or(depth + 1)?;
+        if self.peek() != Some(&Token::Close) {
+          return Err(error("Unclosed '('", position));
+        }
+        expr
+      }
+      _ => return Err(error("Expected a term", position)),
+    };
+    self.pos += 1;
+    Ok(Clause { occur, expr })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Compact form of a parsed query: `+` and `-` for required and excluded
+  /// clauses, `*` for partial words, `|` between alternatives
+  fn show(expr: &Expr) -> String {
+    match expr {
+      Expr::Term { field, word, partial } => format!(
+        "{}{}{}",
+        field.as_ref().map_or(String::new(), |x| format!("{}:", x)),
+        word,
+        if *partial { "*" } else { "" }
+      ),
+      Expr::Phrase { field, phrase } => format!(
+        "{}\"{}\"",
+        field.as_ref().map_or(String::new(), |x| format!("{}:", x)),
+        phrase
+      ),
+      Expr::Group(clauses) => {
+        let clauses: Vec<String> = clauses
+          .iter()
+          .map(|clause| {
+            let occur = match clause.occur {
+              Occur::Should => "",
+              Occur::Must => "+",
+              Occur::MustNot => "-",
+            };
+            format!("{}{}", occur, show(&clause.expr))
+          })
+          .collect();
+        format!("({})", clauses.join(" "))
+      }
+      Expr::Or(exprs) => exprs.iter().map(show).collect::<Vec<_>>().join(" | "),
+    }
+  }
+
+  fn parsed(text: &str) -> String {
+    show(&parse(text).unwrap().unwrap())
+  }
+
+  fn parse_error(text: &str) -> (String, usize) {
+    let err = parse(text).unwrap_err();
+    (err.message, err.position)
+  }
+
+  #[test]
+  fn blank_text_is_none() {
+    assert!(parse("").unwrap().is_none());
+    assert!(parse("   ").unwrap().is_none());
+  }
+
+  #[test]
+  fn words_are_optional_and_the_last_is_partial() {
+    assert_eq!(parsed("anna bell"), "(anna bell*)");
+    assert_eq!(parsed("anna bell "), "(anna bell)");
+  }
+
+  #[test]
+  fn required_and_excluded_words() {
+    assert_eq!(parsed("+blonde -beach day"), "(+blonde -beach day*)");
+  }
+
+  #[test]
+  fn operators_followed_by_a_space_are_words() {
+    assert_eq!(parsed("beach - day"), "(beach - day*)");
+    assert_eq!(parsed("beach + day"), "(beach + day*)");
+    assert_eq!(parsed("beach -"), "(beach -*)");
+    assert_eq!(parsed("(beach -)"), "((beach -))");
+  }
+
+  #[test]
+  fn words_joined_by_punctuation_stay_one_word() {
+    assert_eq!(parsed("anna-bell"), "(anna-bell*)");
+    assert_eq!(parsed("-anna-bell"), "(-anna-bell*)");
+  }
+
+  #[test]
+  fn phrases() {
+    assert_eq!(parsed("\"anna bell\" beach"), "(\"anna bell\" beach*)");
+    assert_eq!(parsed("+\"anna bell\""), "(+\"anna bell\")");
+    assert_eq!(parsed("-\"anna bell\""), "(-\"anna bell\")");
+  }
+
+  #[test]
+  fn fields() {
+    assert_eq!(parsed("actor:ruby beach"), "(actor:ruby beach*)");
+    assert_eq!(parsed("studio:\"vixen media\""), "(studio:\"vixen media\")");
+    assert_eq!(parsed("-scene_name:beach"), "(-scene_name:beach*)");
+    // Only lowercase names are fields, anything else is part of the word
+    assert_eq!(parsed("Actor:ruby"), "(Actor:ruby*)");
+    assert_eq!(parsed("10:30"), "(10:30*)");
+    assert_eq!(parsed("actor:"), "(actor:*)");
+  }
+
+  #[test]
+  fn unknown_fields_are_still_split() {
+    // The index decides whether "re" is a field, and searches "re:zero" as text if not
+    assert_eq!(parsed("re:zero"), "(re:zero*)");
+    match parse("re:zero").unwrap().unwrap() {
+      Expr::Group(clauses) => match &clauses[0].expr {
+        Expr::Term { field, word, .. } => {
+          assert_eq!(field.as_deref(), Some("re"));
+          assert_eq!(word, "zero");
+        }
+        other => panic!("expected a term, got {:?}", other),
+      },
+      other => panic!("expected a group, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn or_separates_alternatives() {
+    assert_eq!(parsed("beach OR pool"), "(beach) | (pool*)");
+    assert_eq!(parsed("anna beach OR pool OR -lake"), "(anna beach) | (pool) | (-lake*)");
+    // Only the uppercase keyword
+    assert_eq!(parsed("beach or pool"), "(beach or pool*)");
+  }
+
+  #[test]
+  fn parentheses_group_clauses() {
+    assert_eq!(parsed("+(beach OR pool) -blonde"), "(+(beach) | (pool) -blonde*)");
+    assert_eq!(parsed("((anna))"), "(((anna)))");
+    assert_eq!(parsed("anna(bell)"), "(anna (bell))");
+  }
+
+  #[test]
+  fn display_writes_query_syntax() {
+    for text in &[
+      "anna bell",
+      "+blonde -beach day",
+      "actor:ruby studio:\"vixen media\"",
+      "anna beach OR pool OR -lake",
+      "+(beach OR pool) -blonde",
+      "((anna)) (bell -(pool lake))",
+    ] {
+      assert_eq!(parse(text).unwrap().unwrap().to_string(), *text);
+    }
+  }
+
+  #[test]
+  fn unterminated_quote() {
+    assert_eq!(parse_error("anna \"bell"), ("Unterminated quote".to_string(), 5));
+    assert_eq!(parse_error("actor:\"anna"), ("Unterminated quote".to_string(), 6));
+  }
+
+  #[test]
+  fn unmatched_close() {
+    assert_eq!(parse_error(")"), ("Unmatched ')'".to_string(), 0));
+    assert_eq!(parse_error("anna) bell"), ("Unmatched ')'".to_string(), 4));
+    assert_eq!(parse_error("(anna)) bell"), ("Unmatched ')'".to_string(), 6));
+    // "+" is a word here, not an operator
+    assert_eq!(parse_error("+)"), ("Unmatched ')'".to_string(), 1));
+  }
+
+  #[test]
+  fn unclosed_open() {
+    assert_eq!(parse_error("(anna"), ("Unclosed '('".to_string(), 0));
+    assert_eq!(parse_error("anna +(bell (beach)"), ("Unclosed '('".to_string(), 6));
+  }
+
+  #[test]
+  fn empty_parentheses() {
+    assert_eq!(parse_error("anna ()"), ("Empty parentheses".to_string(), 6));
+  }
+
+  #[test]
+  fn missing_terms() {
+    assert_eq!(parse_error("OR anna"), ("Expected a term".to_string(), 0));
+    assert_eq!(parse_error("anna OR"), ("Expected a term".to_string(), 7));
+    assert_eq!(parse_error("anna OR OR bell"), ("Expected a term".to_string(), 8));
+    assert_eq!(parse_error("+("), ("Expected a term".to_string(), 2));
+    assert_eq!(parse_error("(anna OR)"), ("Expected a term".to_string(), 8));
+  }
+
+  fn nested(depth: usize) -> String {
+    format!("{}beach{}", "(".repeat(depth), ")".repeat(depth))
+  }
+
+  #[test]
+  fn nesting_up_to_the_limit_parses() {
+    assert!(parse(&nested(MAX_DEPTH)).is_ok());
+  }
+
+  #[test]
+  fn deeper_nesting_is_an_error() {
+    let err = parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+    assert_eq!(err.message, "Too many nested parentheses");
+    assert_eq!(err.position, MAX_DEPTH);
+
+    let err = parse(&"(".repeat(100_000)).unwrap_err();
+    assert_eq!(err.message, "Too many nested parentheses");
+  }
+}
@@ -0,0 +1,651 @@
+use crate::document::{Document, Field};
+use crate::parser::{parse, Expr, ParseError};
+use crate::scoring::Boosts;
+use std::cmp::Ordering;
+use std::fmt;
+use std::vec::Vec;
+
+/// A restriction on the documents returned by a search
+pub enum Filter {
+  /// Boolean field has to be set
+  Flag(String),
+  /// Optional field has to have a value
+  Present(String),
+  /// Numeric field has to be at least the given value, documents without
+  /// a value never match
+  Min(String, i64),
+  /// Numeric field has to be at most the given value, documents without a
+  /// value never match
+  Max(String, i64),
+  /// Reference field has to point to the given id
+  Equals(String, String),
+  /// Reference list has to contain all of the given ids
+  All(String, Vec<String>),
+  /// Reference list must not contain any of the given ids
+  None(String, Vec<String>),
+}
+
+impl Filter {
+  /// Name of the field the filter looks at
+  pub fn field(&self) -> &str {
+    match self {
+      Filter::Flag(name)
+      | Filter::Present(name)
+      | Filter::Min(name, _)
+      | Filter::Max(name, _)
+      | Filter::Equals(name, _)
+      | Filter::All(name, _)
+      | Filter::None(name, _) => name,
+    }
+  }
+
+  pub fn matches<T: Document>(&self, doc: &T) -> bool {
+    match self {
+      Filter::Flag(name) => matches!(doc.field(name), Some(Field::Flag(true))),
+      Filter::Present(name) => match doc.field(name) {
+        Some(Field::Number(value)) => value.is_some(),
+        Some(Field::Ref(value)) => value.is_some(),
+        Some(Field::Flag(value)) => value,
+        _ => false,
+      },
+      Filter::Min(name, min) => match doc.field(name) {
+        Some(Field::Number(Some(value))) => value >= *min,
+        _ => false,
+      },
+      Filter::Max(name, max) => match doc.field(name) {
+        Some(Field::Number(Some(value))) => value <= *max,
+        _ => false,
+      },
+      Filter::Equals(name, id) => match doc.field(name) {
+        Some(Field::Ref(value)) => value.unwrap_or("") == id,
+        _ => false,
+      },
+      Filter::All(name, ids) => match doc.field(name) {
+        Some(Field::Refs(refs)) => ids.iter().all(|id| refs.contains(&id.as_str())),
+        _ => false,
+      },
+      Filter::None(name, ids) => match doc.field(name) {
+        Some(Field::Refs(refs)) => !ids.iter().any(|id| refs.contains(&id.as_str())),
+        _ => false,
+      },
+    }
+  }
+}
+
+impl fmt::Display for Filter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Filter::Flag(name) => write!(f, "{} is set", name),
+      Filter::Present(name) => write!(f, "{} is present", name),
+      Filter::Min(name, min) => write!(f, "{} >= {}", name, min),
+      Filter::Max(name, max) => write!(f, "{} <= {}", name, max),
+      Filter::Equals(name, id) => write!(f, "{} = {}", name, id),
+      Filter::All(name, ids) => write!(f, "{} contain all of {}", name, ids.join(", ")),
+      Filter::None(name, ids) => write!(f, "{} contain none of {}", name, ids.join(", ")),
+    }
+  }
+}
+
+/// What search results are ordered by
+#[derive(Clone, PartialEq)]
+pub enum SortKey {
+  Relevance,
+  Field(String),
+}
+
+/// One key of the order of search results
+#[derive(Clone)]
+pub struct Sort {
+  pub key: SortKey,
+  pub ascending: bool,
+  /// Put documents without a value first instead of last, whatever the direction
+  pub nulls_first: bool,
+}
+
+impl Sort {
+  pub fn new(field: &str, ascending: bool) -> Sort {
+    let key = match field {
+      "relevance" | "score" => SortKey::Relevance,
+      _ => SortKey::Field(field.to_string()),
+    };
+    Sort {
+      key,
+      ascending,
+      nulls_first: false,
+    }
+  }
+
+  /// Parses `field`, optionally followed by `:asc` or `:desc` (the default)
+  /// and `:nulls_first` or `:nulls_last` (the default)
+  fn parse<T: Document>(text: &str, position: usize) -> Result<Sort, ParseError> {
+    let mut parts = text.split(':');
+    let field = parts.next().unwrap_or_default();
+    if field.is_empty() {
+      return Err(ParseError {
+        message: "Missing sort field".to_string(),
+        position,
+      });
+    }
+    if !["relevance", "score"].contains(&field) && !T::FIELDS.contains(&field) {
+      return Err(ParseError {
+        message: format!("Unknown sort field '{}'", field),
+        position,
+      });
+    }
+
+    let mut sort = Sort::new(field, false);
+    for part in parts {
+      match part {
+        "asc" => sort.ascending = true,
+        "desc" => sort.ascending = false,
+        "nulls_first" => sort.nulls_first = true,
+        "nulls_last" => sort.nulls_first = false,
+        _ => {
+          return Err(ParseError {
+            message: format!("Invalid sort option '{}'", part),
+            position,
+          })
+        }
+      }
+    }
+    Ok(sort)
+  }
+
+  /// Value of a document for this key
+  pub fn value<T: Document>(&self, doc: &T, score: f32) -> SortValue {
+    let name = match &self.key {
+      SortKey::Relevance => return SortValue::Score(score),
+      SortKey::Field(name) => name,
+    };
+    match doc.field(name) {
+      Some(Field::Flag(value)) => SortValue::Flag(value),
+      Some(Field::Number(Some(value))) => SortValue::Number(value),
+      Some(Field::Text(value)) => SortValue::Text(value.to_lowercase()),
+      Some(Field::Ref(Some(value))) => SortValue::Text(value.to_string()),
+      Some(Field::Refs(values)) => SortValue::Number(values.len() as i64),
+      _ => SortValue::Null,
+    }
+  }
+
+  pub fn compare(&self, a: &SortValue, b: &SortValue) -> Ordering {
+    let ordering = match (a, b) {
+      (SortValue::Null, SortValue::Null) => return Ordering::Equal,
+      (SortValue::Null, _) => return if self.nulls_first { Ordering::Less } else { Ordering::Greater },
+      (_, SortValue::Null) => return if self.nulls_first { Ordering::Greater } else { Ordering::Less },
+      (SortValue::Flag(a), SortValue::Flag(b)) => a.cmp(b),
+      (SortValue::Number(a), SortValue::Number(b)) => a.cmp(b),
+      (SortValue::Text(a), SortValue::Text(b)) => a.cmp(b),
+      (SortValue::Score(a), SortValue::Score(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+      _ => Ordering::Equal,
+    };
+    if self.ascending {
+      ordering
+    } else {
+      ordering.reverse()
+    }
+  }
+}
+
+/// What a document is sorted by, text is lowercased
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SortValue {
+  Null,
+  Flag(bool),
+  Number(i64),
+  Text(String),
+  Score(f32),
+}
+
+impl fmt::Display for Sort {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.key {
+      SortKey::Relevance => write!(f, "relevance")?,
+      SortKey::Field(name) => write!(f, "{}", name)?,
+    }
+    write!(f, ":{}", if self.ascending { "asc" } else { "desc" })?;
+    if self.nulls_first {
+      write!(f, ":nulls_first")?;
+    }
+    Ok(())
+  }
+}
+
+/// Canonical form of a list of sort keys, an empty one is by relevance
+pub fn sort_spec(sort: &[Sort]) -> String {
+  if sort.is_empty() {
+    return "relevance:desc".to_string();
+  }
+  sort.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// Orders two documents by their values for each sort key, then by id
+pub fn compare_sorted(sort: &[Sort], a: (&[SortValue], &str), b: (&[SortValue], &str)) -> Ordering {
+  sort
+    .iter()
+    .zip(a.0.iter().zip(b.0.iter()))
+    .map(|(key, (a, b))| key.compare(a, b))
+    .find(|x| *x != Ordering::Equal)
+    .unwrap_or_else(|| a.1.cmp(b.1))
+}
+
+/// Position of the last document of a page, the next page starts after it
+#[derive(Serialize, Deserialize)]
+pub struct Cursor {
+  /// Sort the page was ordered by, see `sort_spec`
+  pub sort: String,
+  pub values: Vec<SortValue>,
+  pub id: String,
+}
+
+impl Cursor {
+  /// Opaque form of the cursor, safe to put in a URL
+  pub fn encode(&self) -> String {
+    let json = serde_json::to_vec(self).unwrap();
+    json.iter().map(|x| format!("{:02x}", x)).collect()
+  }
+
+  pub fn decode(text: &str) -> Option<Cursor> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+      return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..text.len())
+      .step_by(2)
+      .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+      .collect();
+    serde_json::from_slice(&bytes?).ok()
+  }
+}
+
+/// Search request against an `Index`.
+///
+/// Without text, all documents are returned. Results are ordered by
+/// relevance unless a sort is given, ties are broken by id.
+pub struct Query {
+  pub text: String,
+  /// Parsed text, `None` if blank
+  pub expr: Option<Expr>,
+  /// Also match indexed tokens a few typos away from the query terms
+  pub fuzzy: bool,
+  /// Also match completions of the last query word
+  pub prefix: bool,
+  /// Field weights replacing those of the index
+  pub boosts: Boosts,
+  /// Record which query terms every hit matched, and where
+  pub with_scores: bool,
+  pub filters: Vec<Filter>,
+  /// Keys to order by, the first one that differs decides
+  pub sort: Vec<Sort>,
+  /// Only return documents sorted after this one
+  pub after: Option<Cursor>,
+  /// Fields whose most common values are counted
+  pub facets: Vec<String>,
+  /// Values returned per facet
+  pub facet_take: usize,
+  /// Fields whose text is returned with the matched words marked
+  pub highlight: Vec<String>,
+  /// Inserted before every matched word
+  pub pre_tag: String,
+  /// Inserted after every matched word
+  pub post_tag: String,
+  /// Id of a document to break down in `SearchResult::explanation`
+  pub explain: Option<String>,
+  pub skip: usize,
+  pub take: usize,
+}
+
+/// Splits a comma separated list of ids
+fn split_ids(list: &str) -> Vec<String> {
+  list.split(',').map(String::from).collect()
+}
+
+impl Query {
+  /// Parses the query text, see `parser::parse` for the syntax
+  pub fn new(text: &str) -> Result<Query, ParseError> {
+    Ok(Query {
+      text: text.to_string(),
+      expr: parse(text)?,
+      fuzzy: false,
+      prefix: false,
+      boosts: Boosts::none(),
+      with_scores: false,
+      filters: Vec::new(),
+      sort: Vec::new(),
+      after: None,
+      facets: Vec::new(),
+      facet_take: 10,
+      highlight: Vec::new(),
+      pre_tag: "<em>".to_string(),
+      post_tag: "</em>".to_string(),
+      explain: None,
+      skip: 0,
+      take: usize::MAX,
+    })
+  }
+
+  pub fn fuzzy(mut self, enabled: Option<bool>) -> Query {
+    self.fuzzy = enabled.unwrap_or(false);
+    self
+  }
+
+  pub fn prefix(mut self, enabled: Option<bool>) -> Query {
+    self.prefix = enabled.unwrap_or(false);
+    self
+  }
+
+  pub fn with_scores(mut self, enabled: Option<bool>) -> Query {
+    self.with_scores = enabled.unwrap_or(false);
+    self
+  }
+
+  /// Overrides field weights from a list like `name:5,label:0.5`, naming
+  /// searchable `fields` or `alias`
+  pub fn boost(mut self, list: Option<String>, fields: &[&str]) -> Result<Query, ParseError> {
+    let list = match list.filter(|x| !x.is_empty()) {
+      Some(list) => list,
+      None => return Ok(self),
+    };
+
+    let mut position = 0;
+    for entry in list.split(',') {
+      let weight = entry.split_once(':').and_then(|(field, weight)| Some((field, weight.parse::<f32>().ok()?)));
+      match weight {
+        Some((field, _)) if field != "alias" && !fields.contains(&field) => {
+          return Err(ParseError {
+            message: format!("Unknown boost field '{}'", field),
+            position,
+          })
+        }
+        Some((field, weight)) if weight >= 0.0 => self.boosts.set(field, weight),
+        _ => {
+          return Err(ParseError {
+            message: format!("Invalid boost '{}'", entry),
+            position,
+          })
+        }
+      }
+      position += entry.len() + 1;
+    }
+    Ok(self)
+  }
+
+  /// Returns `take` hits after skipping `skip`, an empty page is an error
+  /// since it could not be continued
+  pub fn page(mut self, skip: Option<usize>, take: Option<usize>) -> Result<Query, ParseError> {
+    if take == Some(0) {
+      return Err(ParseError {
+        message: "take has to be at least 1".to_string(),
+        position: 0,
+      });
+    }
+    self.skip = skip.unwrap_or(0);
+    self.take = take.unwrap_or(usize::MAX);
+    Ok(self)
+  }
+
+  /// Sorts by the given field, descending unless `dir` is "asc"
+  pub fn sort(mut self, field: Option<String>, dir: Option<String>) -> Query {
+    if let Some(field) = field.filter(|x| !x.is_empty()) {
+      self.sort = vec![Sort::new(&field, dir.as_deref() == Some("asc"))];
+    }
+    self
+  }
+
+  /// Sorts by a list like `rating:desc,release_date:desc:nulls_first,name:asc`
+  /// of fields of `T`, replacing any sort given before
+  pub fn sort_keys<T: Document>(mut self, list: Option<String>) -> Result<Query, ParseError> {
+    if let Some(list) = list.filter(|x| !x.is_empty()) {
+      let mut position = 0;
+      let mut sort = Vec::new();
+      for key in list.split(',') {
+        sort.push(Sort::parse::<T>(key, position)?);
+        position += key.len() + 1;
+      }
+      self.sort = sort;
+    }
+    Ok(self)
+  }
+
+  /// Continues from a cursor returned with a previous page, which has to
+  /// have been sorted the same way. Has to be called after setting the sort.
+  pub fn after(mut self, cursor: Option<String>) -> Result<Query, ParseError> {
+    if let Some(cursor) = cursor.filter(|x| !x.is_empty()) {
+      let cursor = Cursor::decode(&cursor);
+      let error = match &cursor {
+        Some(cursor) if cursor.sort != sort_spec(&self.sort) => Some("Cursor is for another sort"),
+        Some(cursor) if cursor.values.len() == self.sort.len().max(1) => None,
+        _ => Some("Invalid cursor"),
+      };
+      if let Some(message) = error {
+        return Err(ParseError {
+          message: message.to_string(),
+          position: 0,
+        });
+      }
+      self.after = cursor;
+    }
+    Ok(self)
+  }
+
+  /// Counts values of the fields in a comma separated list
+  pub fn facets(mut self, list: Option<String>, take: Option<usize>) -> Query {
+    if let Some(list) = list.filter(|x| !x.is_empty()) {
+      self.facets = split_ids(&list);
+    }
+    if let Some(take) = take {
+      self.facet_take = take;
+    }
+    self
+  }
+
+  /// Highlights matches in the fields of a comma separated list, between
+  /// the given tags or `<em>` and `</em>`
+  pub fn highlight(mut self, list: Option<String>, pre_tag: Option<String>, post_tag: Option<String>) -> Query {
+    if let Some(list) = list.filter(|x| !x.is_empty()) {
+      self.highlight = split_ids(&list);
+    }
+    if let Some(tag) = pre_tag {
+      self.pre_tag = tag;
+    }
+    if let Some(tag) = post_tag {
+      self.post_tag = tag;
+    }
+    self
+  }
+
+  /// Explains why the document with this id is or is not a hit, and how
+  /// its score came about
+  pub fn explain(mut self, id: Option<String>) -> Query {
+    self.explain = id;
+    self
+  }
+
+  pub fn flag(mut self, field: &str, enabled: Option<bool>) -> Query {
+    if enabled == Some(true) {
+      self.filters.push(Filter::Flag(field.to_string()));
+    }
+    self
+  }
+
+  pub fn present(mut self, field: &str, enabled: Option<bool>) -> Query {
+    if enabled == Some(true) {
+      self.filters.push(Filter::Present(field.to_string()));
+    }
+    self
+  }
+
+  pub fn min(mut self, field: &str, value: Option<i64>) -> Query {
+    if let Some(value) = value {
+      self.filters.push(Filter::Min(field.to_string(), value));
+    }
+    self
+  }
+
+  pub fn max(mut self, field: &str, value: Option<i64>) -> Query {
+    if let Some(value) = value {
+      self.filters.push(Filter::Max(field.to_string(), value));
+    }
+    self
+  }
+
+  /// Restricts a numeric field to `min..=max`, either bound may be left out
+  pub fn range(self, field: &str, min: Option<i64>, max: Option<i64>) -> Query {
+    self.min(field, min).max(field, max)
+  }
+
+  pub fn equals(mut self, field: &str, id: Option<String>) -> Query {
+    if let Some(id) = id.filter(|x| !x.is_empty()) {
+      self.filters.push(Filter::Equals(field.to_string(), id));
+    }
+    self
+  }
+
+  /// Requires all ids of a comma separated list
+  pub fn all(mut self, field: &str, list: Option<String>) -> Query {
+    if let Some(list) = list.filter(|x| !x.is_empty()) {
+      self.filters.push(Filter::All(field.to_string(), split_ids(&list)));
+    }
+    self
+  }
+
+  /// Excludes all ids of a comma separated list
+  pub fn none(mut self, field: &str, list: Option<String>) -> Query {
+    if let Some(list) = list.filter(|x| !x.is_empty()) {
+      self.filters.push(Filter::None(field.to_string(), split_ids(&list)));
+    }
+    self
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::scene::StoredScene;
+
+  #[test]
+  fn cursor_round_trip() {
+    let cursor = Cursor {
+      sort: "rating:asc:nulls_first,relevance:desc".to_string(),
+      values: vec![
+        SortValue::Null,
+        SortValue::Flag(true),
+        SortValue::Number(-42),
+        SortValue::Text("anna \"bell\"".to_string()),
+        SortValue::Score(1.5),
+      ],
+      id: "sc_1".to_string(),
+    };
+    let encoded = cursor.encode();
+    assert!(encoded.chars().all(|x| x.is_ascii_hexdigit()));
+
+    let decoded = Cursor::decode(&encoded).unwrap();
+    assert_eq!((decoded.sort.as_str(), decoded.id.as_str()), ("rating:asc:nulls_first,relevance:desc", "sc_1"));
+    assert_eq!(serde_json::to_string(&decoded.values).unwrap(), serde_json::to_string(&cursor.values).unwrap());
+  }
+
+  #[test]
+  fn invalid_cursors_do_not_decode() {
+    assert!(Cursor::decode("").is_none());
+    assert!(Cursor::decode("abc").is_none());
+    assert!(Cursor::decode("zz").is_none());
+    assert!(Cursor::decode("é1").is_none());
+    assert!(Cursor::decode("7b7d").is_none());
+  }
+
+  fn cursor(sort: &str, values: Vec<SortValue>) -> Option<String> {
+    let cursor = Cursor {
+      sort: sort.to_string(),
+      values,
+      id: "sc_1".to_string(),
+    };
+    Some(cursor.encode())
+  }
+
+  fn sorted(list: &str) -> Query {
+    Query::new("").unwrap().sort_keys::<StoredScene>(Some(list.into())).unwrap()
+  }
+
+  #[test]
+  fn after_needs_a_value_per_sort_key() {
+    assert!(Query::new("").unwrap().after(cursor("relevance:desc", vec![SortValue::Score(1.0)])).is_ok());
+
+    let err = sorted("rating,name").after(cursor("rating:desc,name:desc", vec![SortValue::Number(3)]));
+    assert_eq!(err.err().unwrap().message, "Invalid cursor");
+  }
+
+  #[test]
+  fn after_needs_the_same_sort() {
+    let values = || vec![SortValue::Number(3), SortValue::Text("anna".into())];
+    assert!(sorted("rating:desc,name:asc").after(cursor("rating:desc,name:asc", values())).is_ok());
+    // Spelled differently, sorted the same way
+    assert!(sorted("rating,name:asc:nulls_last").after(cursor("rating:desc,name:asc", values())).is_ok());
+
+    for other in &["rating:asc,name:asc", "rating:desc,name:asc:nulls_first", "name:asc,rating:desc"] {
+      let err = sorted(other).after(cursor("rating:desc,name:asc", values()));
+      assert_eq!(err.err().unwrap().message, "Cursor is for another sort", "{}", other);
+    }
+    let err = Query::new("").unwrap().after(cursor("rating:desc", vec![SortValue::Number(3)]));
+    assert_eq!(err.err().unwrap().message, "Cursor is for another sort");
+  }
+
+  #[test]
+  fn empty_pages_are_rejected() {
+    assert!(Query::new("").unwrap().page(Some(5), None).is_ok());
+    let err = Query::new("").unwrap().page(None, Some(0)).err().unwrap();
+    assert_eq!(err.message, "take has to be at least 1");
+  }
+
+  #[test]
+  fn sort_keys_parse_direction_and_nulls() {
+    let query = Query::new("")
+      .unwrap()
+      .sort_keys::<StoredScene>(Some("rating:asc:nulls_first,score".into()))
+      .unwrap();
+    assert_eq!(query.sort.len(), 2);
+    assert!(query.sort[0].key == SortKey::Field("rating".into()) && query.sort[0].ascending && query.sort[0].nulls_first);
+    assert!(query.sort[1].key == SortKey::Relevance && !query.sort[1].ascending);
+
+    let err = Query::new("").unwrap().sort_keys::<StoredScene>(Some("rating,name:up".into())).err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Invalid sort option 'up'", 7));
+  }
+
+  #[test]
+  fn boost_accepts_searchable_fields_and_alias() {
+    let boost = |list: &str| Query::new("").unwrap().boost(Some(list.into()), StoredScene::TEXT_FIELDS);
+    let query = boost("name:5,alias:0").unwrap();
+    assert_eq!((query.boosts.get("name"), query.boosts.get("alias")), (Some(5.0), Some(0.0)));
+
+    let err = boost("name:5,nmae:5").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Unknown boost field 'nmae'", 7));
+    let err = boost("scene_name:2").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Unknown boost field 'scene_name'", 0));
+    let err = boost("name:-1").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Invalid boost 'name:-1'", 0));
+    let err = boost("actor").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Invalid boost 'actor'", 0));
+  }
+
+  #[test]
+  fn sort_keys_reject_unknown_fields() {
+    let sort = |list: &str| Query::new("").unwrap().sort_keys::<StoredScene>(Some(list.into()));
+    assert!(sort("addedOn,views:asc,relevance").is_ok());
+
+    let err = sort("bogus").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Unknown sort field 'bogus'", 0));
+    let err = sort("name:asc,ratng:desc").err().unwrap();
+    assert_eq!((err.message.as_str(), err.position), ("Unknown sort field 'ratng'", 9));
+    // Legacy sorting stays lenient
+    assert_eq!(Query::new("").unwrap().sort(Some("bogus".into()), None).sort.len(), 1);
+  }
+
+  #[test]
+  fn nulls_sort_last_in_both_directions() {
+    for ascending in &[true, false] {
+      let sort = Sort {
+        key: SortKey::Field("rating".into()),
+        ascending: *ascending,
+        nulls_first: false,
+      };
+      assert_eq!(sort.compare(&SortValue::Null, &SortValue::Number(1)), Ordering::Greater);
+    }
+  }
+}
@@ -0,0 +1,104 @@
+use super::{apply, open_store, remove_many, search_store, suggest, Filters, SearchForm, SearchResponse};
+use lazy_static::lazy_static;
+use rocket::http::RawStr;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+use std::sync::Mutex;
+use std::vec::Vec;
+use twigs::actor::{InputActor, StoredActor};
+use twigs::query::Query;
+use twigs::store::Store;
+use twigs::wal::Operation;
+
+lazy_static! {
+  pub static ref ACTORS: Mutex<Store<StoredActor>> = Mutex::new(open_store("actor"));
+}
+
+/// Filters of an actor search, besides those of every index
+#[derive(FromForm)]
+struct ActorFilters {
+  include: Option<String>,
+  exclude: Option<String>,
+}
+
+impl Filters for ActorFilters {
+  fn apply(self, query: Query) -> Query {
+    query
+      .all("labels", self.include)
+      .none("labels", self.exclude)
+  }
+}
+
+#[put("/<id>", data = "<inputs>")]
+fn update_actor(id: &RawStr, inputs: Json<InputActor>) -> Result<(), Status> {
+  if inputs.id != id.as_str() {
+    return Err(Status::BadRequest);
+  }
+
+  let mut actors = ACTORS.lock().unwrap();
+
+  if !actors.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut actors, Operation::Update(id.to_string(), inputs.into_inner()))
+}
+
+#[delete("/<id>")]
+fn delete_actor(id: &RawStr) -> Result<(), Status> {
+  println!("Deleting {}", id.as_str());
+
+  let mut actors = ACTORS.lock().unwrap();
+
+  if !actors.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut actors, Operation::Remove(id.to_string()))
+}
+
+#[post("/delete", format = "json", data = "<ids>")]
+fn delete_actors(ids: Json<Vec<String>>) -> Result<Json<JsonValue>, Status> {
+  println!("Deleting {} actors", ids.len());
+
+  remove_many(&mut ACTORS.lock().unwrap(), ids.into_inner())
+}
+
+#[delete("/")]
+fn clear_actors() -> Result<(), Status> {
+  println!("Clearing actor index...");
+
+  apply(&mut ACTORS.lock().unwrap(), Operation::Clear)
+}
+
+#[get("/info")]
+fn get_actors_info() -> Json<JsonValue> {
+  Json(json!(ACTORS.lock().unwrap().index().info()))
+}
+
+#[get("/suggest?<query>&<take>")]
+fn suggest_actors(query: String, take: Option<usize>) -> SearchResponse {
+  suggest(ACTORS.lock().unwrap().index(), &query, take)
+}
+
+#[get("/?<search..>")]
+fn get_actors(search: SearchForm<ActorFilters>) -> SearchResponse {
+  search_store(&ACTORS, "actors", search)
+}
+
+#[post("/", format = "json", data = "<inputs>")]
+fn create_actors(inputs: Json<Vec<InputActor>>) -> Result<Json<JsonValue>, Status> {
+  println!("Received new actors");
+
+  let mut actors = ACTORS.lock().unwrap();
+  apply(&mut actors, Operation::Insert(inputs.into_inner()))?;
+
+  Ok(Json(json!(actors.index().info())))
+}
+
+/// Loads the actor index before the first request comes in
+pub fn load() {
+  lazy_static::initialize(&ACTORS);
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![get_actors, suggest_actors, create_actors, delete_actor, delete_actors, clear_actors, update_actor, get_actors_info]
+}
@@ -0,0 +1,121 @@
+use super::{apply, explain_store, open_store, remove_many, search_store, suggest, ExplainResponse, Filters, SearchForm, SearchResponse};
+use lazy_static::lazy_static;
+use rocket::http::RawStr;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+use std::sync::Mutex;
+use std::vec::Vec;
+use twigs::query::Query;
+use twigs::image::{InputImage, StoredImage};
+use twigs::store::Store;
+use twigs::wal::Operation;
+
+lazy_static! {
+  pub static ref IMAGES: Mutex<Store<StoredImage>> = Mutex::new(open_store("image"));
+}
+
+/// Filters of an image search, besides those of every index
+#[derive(FromForm)]
+struct ImageFilters {
+  rating_max: Option<u8>,
+  added_on_min: Option<i64>,
+  added_on_max: Option<i64>,
+  bookmark_min: Option<i64>,
+  bookmark_max: Option<i64>,
+  include: Option<String>,
+  exclude: Option<String>,
+  scene: Option<String>,
+  actors: Option<String>,
+}
+
+impl Filters for ImageFilters {
+  fn apply(self, query: Query) -> Query {
+    query
+      .max("rating", self.rating_max.map(i64::from))
+      .range("added_on", self.added_on_min, self.added_on_max)
+      .range("bookmark", self.bookmark_min, self.bookmark_max)
+      .all("labels", self.include)
+      .all("actors", self.actors)
+      .equals("scene", self.scene)
+      .none("labels", self.exclude)
+  }
+}
+
+#[delete("/")]
+fn clear_images() -> Result<(), Status> {
+  println!("Clearing image index...");
+
+  apply(&mut IMAGES.lock().unwrap(), Operation::Clear)
+}
+
+#[put("/<id>", data = "<inputs>")]
+fn update_image(id: &RawStr, inputs: Json<InputImage>) -> Result<(), Status> {
+  if inputs.id != id.as_str() {
+    return Err(Status::BadRequest);
+  }
+
+  let mut images = IMAGES.lock().unwrap();
+
+  if !images.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut images, Operation::Update(id.to_string(), inputs.into_inner()))
+}
+
+#[delete("/<id>")]
+fn delete_image(id: &RawStr) -> Result<(), Status> {
+  println!("Deleting {}", id.as_str());
+
+  let mut images = IMAGES.lock().unwrap();
+
+  if !images.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut images, Operation::Remove(id.to_string()))
+}
+
+#[post("/delete", format = "json", data = "<ids>")]
+fn delete_images(ids: Json<Vec<String>>) -> Result<Json<JsonValue>, Status> {
+  println!("Deleting {} images", ids.len());
+
+  remove_many(&mut IMAGES.lock().unwrap(), ids.into_inner())
+}
+
+#[get("/info")]
+fn get_images_info() -> Json<JsonValue> {
+  Json(json!(IMAGES.lock().unwrap().index().info()))
+}
+
+#[get("/suggest?<query>&<take>")]
+fn suggest_images(query: String, take: Option<usize>) -> SearchResponse {
+  suggest(IMAGES.lock().unwrap().index(), &query, take)
+}
+
+#[get("/?<search..>")]
+fn get_images(search: SearchForm<ImageFilters>) -> SearchResponse {
+  search_store(&IMAGES, "images", search)
+}
+
+#[get("/explain?<id>&<search..>")]
+fn explain_image(id: String, search: SearchForm<ImageFilters>) -> ExplainResponse {
+  explain_store(&IMAGES, "image", id, search)
+}
+
+#[post("/", format = "json", data = "<inputs>")]
+fn create_images(inputs: Json<Vec<InputImage>>) -> Result<Json<JsonValue>, Status> {
+  println!("Received new images");
+
+  let mut images = IMAGES.lock().unwrap();
+  apply(&mut images, Operation::Insert(inputs.into_inner()))?;
+
+  Ok(Json(json!(images.index().info())))
+}
+
+/// Loads the image index before the first request comes in
+pub fn load() {
+  lazy_static::initialize(&IMAGES);
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![get_images, explain_image, suggest_images, create_images, delete_image, delete_images, clear_images, update_image, get_images_info]
+}
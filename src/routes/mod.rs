@@ -0,0 +1,247 @@
+use self::response::{explain_response, search_response};
+use lazy_static::lazy_static;
+use rocket::http::Status;
+use rocket::request::{self, FormItems, FormParseError, FromForm, FromQuery};
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::{Json, JsonValue};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Instant;
+use twigs::config::Config;
+use twigs::document::{Document, Field};
+use twigs::index::Index;
+use twigs::parser::ParseError;
+use twigs::query::Query;
+use twigs::store::Store;
+use twigs::tokenizer::partial_word;
+use twigs::wal::Operation;
+
+pub mod actor;
+pub mod image;
+pub mod movie;
+pub mod scene;
+pub mod search;
+pub mod studio;
+
+mod response;
+
+/// Search results, or a 400 describing invalid query syntax
+pub type SearchResponse = Result<Json<JsonValue>, BadRequest<Json<JsonValue>>>;
+
+/// Breakdown of a document for a search, 404 if the document is unknown
+pub type ExplainResponse = Result<Option<Json<JsonValue>>, BadRequest<Json<JsonValue>>>;
+
+lazy_static! {
+  pub static ref CONFIG: Config = Config::from_env();
+}
+
+/// Search parameters every index takes
+#[derive(FromForm)]
+pub struct IndexParams {
+  query: Option<String>,
+  fuzzy: Option<bool>,
+  prefix: Option<bool>,
+  with_scores: Option<bool>,
+  boost: Option<String>,
+  take: Option<usize>,
+  skip: Option<usize>,
+  after: Option<String>,
+  facets: Option<String>,
+  facet_take: Option<usize>,
+  highlight: Option<String>,
+  pre_tag: Option<String>,
+  post_tag: Option<String>,
+  sort: Option<String>,
+  sort_by: Option<String>,
+  sort_dir: Option<String>,
+  bookmark: Option<bool>,
+  favorite: Option<bool>,
+  rating: Option<u8>,
+}
+
+/// Search parameters only some indexes take
+pub trait Filters {
+  /// Adds the filters that were given to `query`
+  fn apply(self, query: Query) -> Query;
+}
+
+/// Query string of a search, read both as `IndexParams` and as the
+/// filters of the index. Unknown parameters are ignored.
+pub struct SearchForm<F> {
+  params: IndexParams,
+  filters: F,
+}
+
+impl<'q, F> FromQuery<'q> for SearchForm<F>
+where
+  F: FromForm<'q, Error = FormParseError<'q>>,
+{
+  type Error = FormParseError<'q>;
+
+  fn from_query(query: request::Query<'q>) -> Result<Self, Self::Error> {
+    Ok(SearchForm {
+      params: IndexParams::from_form(&mut FormItems::from(query.0), false)?,
+      filters: F::from_form(&mut FormItems::from(query.0), false)?,
+    })
+  }
+}
+
+/// Query for the common parameters and the filters of an index
+pub fn build_query<T: Document, F: Filters>(form: SearchForm<F>) -> Result<Query, BadRequest<Json<JsonValue>>> {
+  let SearchForm { params, filters } = form;
+  let query = Query::new(&params.query.unwrap_or_default())
+    .map_err(invalid_query)?
+    .boost(params.boost, T::TEXT_FIELDS)
+    .map_err(invalid_query)?
+    .fuzzy(params.fuzzy)
+    .prefix(params.prefix)
+    .with_scores(params.with_scores)
+    .page(params.skip, params.take)
+    .map_err(invalid_query)?
+    .sort(params.sort_by, params.sort_dir)
+    .sort_keys::<T>(params.sort)
+    .map_err(invalid_query)?
+    .after(params.after)
+    .map_err(invalid_query)?
+    .facets(params.facets, params.facet_take)
+    .highlight(params.highlight, params.pre_tag, params.post_tag)
+    .flag("favorite", params.favorite)
+    .present("bookmark", params.bookmark)
+    .min("rating", rating_min(params.rating));
+  Ok(filters.apply(query))
+}
+
+/// Searches the index of a store, `kind` names its documents in the log
+pub fn search_store<T, F>(store: &Mutex<Store<T>>, kind: &str, form: SearchForm<F>) -> SearchResponse
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+  F: Filters,
+{
+  let now = Instant::now();
+  let query = build_query::<T, F>(form)?;
+  println!("Searching {} for {}", kind, query.text);
+
+  let store = store.lock().unwrap();
+  let result = store.index().search(&query);
+
+  Ok(search_response(&query, now, &result))
+}
+
+/// Explains how the document `id` fares in a search of a store
+pub fn explain_store<T, F>(store: &Mutex<Store<T>>, kind: &str, id: String, form: SearchForm<F>) -> ExplainResponse
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+  F: Filters,
+{
+  let now = Instant::now();
+  let query = build_query::<T, F>(form)?.explain(Some(id));
+  println!("Explaining {} search for {}", kind, query.text);
+
+  let store = store.lock().unwrap();
+  let result = store.index().search(&query);
+
+  Ok(explain_response(&query, now, &result))
+}
+
+/// Opens the store of an index, replaying its log
+pub fn open_store<T>(name: &str) -> Store<T>
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+{
+  match Store::open(&CONFIG.data_dir, name, CONFIG.snapshot_interval) {
+    Ok(mut store) => {
+      store.set_bm25(CONFIG.bm25(name));
+      store.set_boosts(CONFIG.boosts(name));
+      println!("Loaded {} {}s", store.index().len(), name);
+      store
+    }
+    Err(err) => panic!("Failed to load {} index: {}", name, err),
+  }
+}
+
+/// Logs and applies an operation, a failed write is reported as 500
+pub fn apply<T>(store: &mut Store<T>, op: Operation<T::Input>) -> Result<(), Status>
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+{
+  store.apply(op).map_err(|err| {
+    println!("Failed to write log: {}", err);
+    Status::InternalServerError
+  })
+}
+
+/// Removes the documents with the given ids, logging a single operation
+/// for all of them. Reports how many were removed and which ids are unknown.
+pub fn remove_many<T>(store: &mut Store<T>, ids: Vec<String>) -> Result<Json<JsonValue>, Status>
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+{
+  let (mut found, not_found): (Vec<String>, Vec<String>) =
+    ids.into_iter().partition(|id| store.index().contains(id));
+  found.sort();
+  found.dedup();
+
+  let removed = found.len();
+  if !found.is_empty() {
+    apply(store, Operation::RemoveMany(found))?;
+  }
+  Ok(Json(json!({ "removed": removed, "not_found": not_found })))
+}
+
+/// Minimum rating of the `rating` parameter. `rating=0` used to let
+/// unrated documents through, so it does not filter at all.
+pub fn rating_min(rating: Option<u8>) -> Option<i64> {
+  rating.filter(|x| *x > 0).map(i64::from)
+}
+
+/// Invalid query syntax is reported as 400, along with where the problem is
+pub fn invalid_query(error: ParseError) -> BadRequest<Json<JsonValue>> {
+  println!("Invalid query: {}", error);
+  BadRequest(Some(Json(json!({ "error": error }))))
+}
+
+/// Completions of the word being typed at the end of `text`, and the best
+/// documents matching `text` with that word completed
+pub fn suggest<T: Document>(index: &Index<T>, text: &str, take: Option<usize>) -> SearchResponse {
+  let now = Instant::now();
+  let take = take.unwrap_or(10);
+
+  let terms = match partial_word(text) {
+    Some(word) => index.suggest(&word, take),
+    None => Vec::new(),
+  };
+
+  let query = Query::new(text)
+    .map_err(invalid_query)?
+    .prefix(Some(true))
+    .page(None, Some(take))
+    .map_err(invalid_query)?;
+  let items: Vec<JsonValue> = index
+    .search(&query)
+    .items
+    .iter()
+    .map(|hit| {
+      let name = match hit.doc.field("name") {
+        Some(Field::Text(name)) => name,
+        _ => "",
+      };
+      json!({ "id": hit.doc.id(), "name": name })
+    })
+    .collect();
+
+  Ok(Json(json!({
+    "query": text,
+    "time": {
+      "milli": now.elapsed().as_millis() as u64,
+      "micro": now.elapsed().as_micros() as u64,
+    },
+    "terms": terms,
+    "items": items
+  })))
+}
@@ -0,0 +1,111 @@
+use super::{apply, open_store, remove_many, search_store, suggest, Filters, SearchForm, SearchResponse};
+use lazy_static::lazy_static;
+use rocket::http::RawStr;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+use std::sync::Mutex;
+use std::vec::Vec;
+use twigs::query::Query;
+use twigs::movie::{InputMovie, StoredMovie};
+use twigs::store::Store;
+use twigs::wal::Operation;
+
+lazy_static! {
+  pub static ref MOVIES: Mutex<Store<StoredMovie>> = Mutex::new(open_store("movie"));
+}
+
+/// Filters of a movie search, besides those of every index
+#[derive(FromForm)]
+struct MovieFilters {
+  include: Option<String>,
+  exclude: Option<String>,
+  studio: Option<String>,
+  actors: Option<String>,
+  duration_min: Option<u32>,
+  duration_max: Option<u32>,
+}
+
+impl Filters for MovieFilters {
+  fn apply(self, query: Query) -> Query {
+    query
+      .range("duration", self.duration_min.map(i64::from), self.duration_max.map(i64::from))
+      .equals("studio", self.studio)
+      .all("labels", self.include)
+      .all("actors", self.actors)
+      .none("labels", self.exclude)
+  }
+}
+
+#[put("/<id>", data = "<inputs>")]
+fn update_movie(id: &RawStr, inputs: Json<InputMovie>) -> Result<(), Status> {
+  if inputs.id != id.as_str() {
+    return Err(Status::BadRequest);
+  }
+
+  let mut movies = MOVIES.lock().unwrap();
+
+  if !movies.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut movies, Operation::Update(id.to_string(), inputs.into_inner()))
+}
+
+#[delete("/<id>")]
+fn delete_movie(id: &RawStr) -> Result<(), Status> {
+  println!("Deleting {}", id.as_str());
+
+  let mut movies = MOVIES.lock().unwrap();
+
+  if !movies.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut movies, Operation::Remove(id.to_string()))
+}
+
+#[post("/delete", format = "json", data = "<ids>")]
+fn delete_movies(ids: Json<Vec<String>>) -> Result<Json<JsonValue>, Status> {
+  println!("Deleting {} movies", ids.len());
+
+  remove_many(&mut MOVIES.lock().unwrap(), ids.into_inner())
+}
+
+#[delete("/")]
+fn clear_movies() -> Result<(), Status> {
+  println!("Clearing movie index...");
+
+  apply(&mut MOVIES.lock().unwrap(), Operation::Clear)
+}
+
+#[get("/info")]
+fn get_movies_info() -> Json<JsonValue> {
+  Json(json!(MOVIES.lock().unwrap().index().info()))
+}
+
+#[get("/suggest?<query>&<take>")]
+fn suggest_movies(query: String, take: Option<usize>) -> SearchResponse {
+  suggest(MOVIES.lock().unwrap().index(), &query, take)
+}
+
+#[get("/?<search..>")]
+fn get_movies(search: SearchForm<MovieFilters>) -> SearchResponse {
+  search_store(&MOVIES, "movies", search)
+}
+
+#[post("/", format = "json", data = "<inputs>")]
+fn create_movies(inputs: Json<Vec<InputMovie>>) -> Result<Json<JsonValue>, Status> {
+  println!("Received new movies");
+
+  let mut movies = MOVIES.lock().unwrap();
+  apply(&mut movies, Operation::Insert(inputs.into_inner()))?;
+
+  Ok(Json(json!(movies.index().info())))
+}
+
+/// Loads the movie index before the first request comes in
+pub fn load() {
+  lazy_static::initialize(&MOVIES);
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![get_movies, suggest_movies, create_movies, delete_movie, delete_movies, clear_movies, update_movie, get_movies_info]
+}
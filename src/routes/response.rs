@@ -0,0 +1,59 @@
+use rocket_contrib::json::{Json, JsonValue};
+use std::time::Instant;
+use twigs::document::Document;
+use twigs::index::SearchResult;
+use twigs::query::Query;
+
+pub fn search_response<T: Document>(query: &Query, now: Instant, result: &SearchResult<T>) -> Json<JsonValue> {
+  // Plain ids unless scores or highlights were asked for
+  let items: Vec<JsonValue> = if query.with_scores || !query.highlight.is_empty() {
+    result
+      .items
+      .iter()
+      .map(|x| {
+        let mut item = json!({ "id": x.doc.id() });
+        if query.with_scores {
+          item["score"] = json!(x.score).0;
+          item["matches"] = json!(x.matches).0;
+        }
+        if !query.highlight.is_empty() {
+          item["highlight"] = json!(x.highlights).0;
+        }
+        item
+      })
+      .collect()
+  } else {
+    result.items.iter().map(|x| json!(x.doc.id())).collect()
+  };
+
+  Json(json!({
+    "query": query.text,
+    "time": {
+      "sec": now.elapsed().as_secs(),
+      "milli": now.elapsed().as_millis() as u64,
+      "micro": now.elapsed().as_micros() as u64,
+    },
+    "num_hits": result.num_hits,
+    "max_score": result.max_score,
+    "items": items,
+    "facets": result.facets,
+    "next": result.next
+  }))
+}
+
+/// Breakdown of `Query::explain`, `None` if the document is unknown
+pub fn explain_response<T: Document>(query: &Query, now: Instant, result: &SearchResult<T>) -> Option<Json<JsonValue>> {
+  let explanation = result.explanation.as_ref()?;
+
+  Some(Json(json!({
+    "query": query.text,
+    "time": {
+      "sec": now.elapsed().as_secs(),
+      "milli": now.elapsed().as_millis() as u64,
+      "micro": now.elapsed().as_micros() as u64,
+    },
+    "num_hits": result.num_hits,
+    "max_score": result.max_score,
+    "explanation": explanation
+  })))
+}
@@ -0,0 +1,136 @@
+use super::{apply, explain_store, open_store, remove_many, search_store, suggest, ExplainResponse, Filters, SearchForm, SearchResponse};
+use lazy_static::lazy_static;
+use rocket::http::RawStr;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+use std::sync::Mutex;
+use std::vec::Vec;
+use twigs::query::Query;
+use twigs::scene::{InputScene, StoredScene};
+use twigs::store::Store;
+use twigs::wal::Operation;
+
+lazy_static! {
+  pub static ref SCENES: Mutex<Store<StoredScene>> = Mutex::new(open_store("scene"));
+}
+
+/// Filters of a scene search, besides those of every index
+#[derive(FromForm)]
+struct SceneFilters {
+  rating_max: Option<u8>,
+  added_on_min: Option<i64>,
+  added_on_max: Option<i64>,
+  release_date_min: Option<i64>,
+  release_date_max: Option<i64>,
+  bookmark_min: Option<i64>,
+  bookmark_max: Option<i64>,
+  size_min: Option<i64>,
+  size_max: Option<i64>,
+  resolution_min: Option<u16>,
+  resolution_max: Option<u16>,
+  num_watches_min: Option<u16>,
+  num_watches_max: Option<u16>,
+  include: Option<String>,
+  exclude: Option<String>,
+  studio: Option<String>,
+  actors: Option<String>,
+  duration_min: Option<u16>,
+  duration_max: Option<u16>,
+}
+
+impl Filters for SceneFilters {
+  fn apply(self, query: Query) -> Query {
+    query
+      .max("rating", self.rating_max.map(i64::from))
+      .range("added_on", self.added_on_min, self.added_on_max)
+      .range("release_date", self.release_date_min, self.release_date_max)
+      .range("bookmark", self.bookmark_min, self.bookmark_max)
+      .range("size", self.size_min, self.size_max)
+      .range("resolution", self.resolution_min.map(i64::from), self.resolution_max.map(i64::from))
+      .range("num_watches", self.num_watches_min.map(i64::from), self.num_watches_max.map(i64::from))
+      .range("duration", self.duration_min.map(i64::from), self.duration_max.map(i64::from))
+      .equals("studio", self.studio)
+      .all("labels", self.include)
+      .all("actors", self.actors)
+      .none("labels", self.exclude)
+  }
+}
+
+#[put("/<id>", data = "<inputs>")]
+fn update_scene(id: &RawStr, inputs: Json<InputScene>) -> Result<(), Status> {
+  if inputs.id != id.as_str() {
+    return Err(Status::BadRequest);
+  }
+
+  let mut scenes = SCENES.lock().unwrap();
+
+  if !scenes.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut scenes, Operation::Update(id.to_string(), inputs.into_inner()))
+}
+
+#[delete("/<id>")]
+fn delete_scene(id: &RawStr) -> Result<(), Status> {
+  println!("Deleting {}", id.as_str());
+
+  let mut scenes = SCENES.lock().unwrap();
+
+  if !scenes.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut scenes, Operation::Remove(id.to_string()))
+}
+
+#[post("/delete", format = "json", data = "<ids>")]
+fn delete_scenes(ids: Json<Vec<String>>) -> Result<Json<JsonValue>, Status> {
+  println!("Deleting {} scenes", ids.len());
+
+  remove_many(&mut SCENES.lock().unwrap(), ids.into_inner())
+}
+
+#[delete("/")]
+fn clear_scenes() -> Result<(), Status> {
+  println!("Clearing scene index...");
+
+  apply(&mut SCENES.lock().unwrap(), Operation::Clear)
+}
+
+#[get("/info")]
+fn get_scenes_info() -> Json<JsonValue> {
+  Json(json!(SCENES.lock().unwrap().index().info()))
+}
+
+#[get("/suggest?<query>&<take>")]
+fn suggest_scenes(query: String, take: Option<usize>) -> SearchResponse {
+  suggest(SCENES.lock().unwrap().index(), &query, take)
+}
+
+#[get("/?<search..>")]
+fn get_scenes(search: SearchForm<SceneFilters>) -> SearchResponse {
+  search_store(&SCENES, "scenes", search)
+}
+
+#[get("/explain?<id>&<search..>")]
+fn explain_scene(id: String, search: SearchForm<SceneFilters>) -> ExplainResponse {
+  explain_store(&SCENES, "scene", id, search)
+}
+
+#[post("/", format = "json", data = "<inputs>")]
+fn create_scenes(inputs: Json<Vec<InputScene>>) -> Result<Json<JsonValue>, Status> {
+  println!("Received new scenes");
+
+  let mut scenes = SCENES.lock().unwrap();
+  apply(&mut scenes, Operation::Insert(inputs.into_inner()))?;
+
+  Ok(Json(json!(scenes.index().info())))
+}
+
+/// Loads the scene index before the first request comes in
+pub fn load() {
+  lazy_static::initialize(&SCENES);
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![get_scenes, explain_scene, suggest_scenes, create_scenes, delete_scene, delete_scenes, clear_scenes, update_scene, get_scenes_info]
+}
@@ -0,0 +1,161 @@
+use super::actor::ACTORS;
+use super::image::IMAGES;
+use super::movie::MOVIES;
+use super::scene::SCENES;
+use super::studio::STUDIOS;
+use super::{invalid_query, SearchResponse};
+use rocket::request::LenientForm;
+use rocket_contrib::json::{Json, JsonValue};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use std::vec::Vec;
+use twigs::actor::StoredActor;
+use twigs::document::Document;
+use twigs::image::StoredImage;
+use twigs::movie::StoredMovie;
+use twigs::parser::ParseError;
+use twigs::query::Query;
+use twigs::scene::StoredScene;
+use twigs::store::Store;
+use twigs::studio::StoredStudio;
+
+/// Indexes taking part in a search unless restricted with `types`
+const TYPES: &[&str] = &["scene", "image", "actor", "studio", "movie"];
+
+#[derive(FromForm)]
+struct SearchParams {
+  query: Option<String>,
+  fuzzy: Option<bool>,
+  prefix: Option<bool>,
+  boost: Option<String>,
+  take: Option<usize>,
+  skip: Option<usize>,
+  types: Option<String>,
+}
+
+/// A hit of any index
+struct Hit {
+  kind: &'static str,
+  id: String,
+  score: f32,
+}
+
+/// Parses a comma separated list of index names
+fn parse_types(list: Option<String>) -> Result<Vec<&'static str>, ParseError> {
+  let list = match list.filter(|x| !x.is_empty()) {
+    Some(list) => list,
+    None => return Ok(TYPES.to_vec()),
+  };
+
+  let mut types = Vec::new();
+  let mut position = 0;
+  for name in list.split(',') {
+    match TYPES.iter().find(|x| **x == name) {
+      Some(kind) if !types.contains(kind) => types.push(*kind),
+      Some(_) => {}
+      None => {
+        return Err(ParseError {
+          message: format!("Unknown type '{}'", name),
+          position,
+        })
+      }
+    }
+    position += name.len() + 1;
+  }
+  Ok(types)
+}
+
+/// Searchable fields of any index, which can be boosted
+fn boost_fields() -> Vec<&'static str> {
+  [
+    StoredScene::TEXT_FIELDS,
+    StoredImage::TEXT_FIELDS,
+    StoredActor::TEXT_FIELDS,
+    StoredStudio::TEXT_FIELDS,
+    StoredMovie::TEXT_FIELDS,
+  ]
+  .concat()
+}
+
+/// Collects the hits of one index with normalized scores, returns their
+/// total number
+fn search_index<T>(store: &Mutex<Store<T>>, kind: &'static str, query: &Query, hits: &mut Vec<Hit>) -> usize
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+{
+  let store = store.lock().unwrap();
+  let result = store.index().search(query);
+  hits.extend(result.items.iter().map(|hit| Hit {
+    kind,
+    id: hit.doc.id().to_string(),
+    score: hit.normalized_score(result.max_score),
+  }));
+  result.num_hits
+}
+
+#[get("/?<params..>")]
+fn search(params: LenientForm<SearchParams>) -> SearchResponse {
+  let params = params.into_inner();
+  let s = params.query.unwrap_or_default();
+  println!("Searching everything for {}", s);
+  let now = Instant::now();
+
+  let types = parse_types(params.types).map_err(invalid_query)?;
+
+  // This backs the search bar, so typos are tolerated unless `fuzzy=false`
+  let query = Query::new(&s)
+    .map_err(invalid_query)?
+    .boost(params.boost, &boost_fields())
+    .map_err(invalid_query)?
+    .fuzzy(Some(params.fuzzy.unwrap_or(true)))
+    .prefix(params.prefix)
+    .page(params.skip, params.take)
+    .map_err(invalid_query)?;
+
+  // Every index contributes its best hits up to the end of the page
+  let (skip, take) = (query.skip, query.take);
+  let query = query.page(None, Some(skip.saturating_add(take))).map_err(invalid_query)?;
+
+  let mut hits = Vec::new();
+  let mut counts = BTreeMap::new();
+  for kind in types {
+    let num_hits = match kind {
+      "scene" => search_index(&SCENES, kind, &query, &mut hits),
+      "image" => search_index(&IMAGES, kind, &query, &mut hits),
+      "actor" => search_index(&ACTORS, kind, &query, &mut hits),
+      "studio" => search_index(&STUDIOS, kind, &query, &mut hits),
+      "movie" => search_index(&MOVIES, kind, &query, &mut hits),
+      _ => unreachable!(),
+    };
+    counts.insert(kind, num_hits);
+  }
+
+  hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+  let items: Vec<JsonValue> = hits
+    .into_iter()
+    .skip(skip)
+    .take(take)
+    .map(|hit| json!({ "type": hit.kind, "id": hit.id, "score": hit.score }))
+    .collect();
+
+  Ok(Json(json!({
+    "query": s,
+    "time": {
+      "sec": now.elapsed().as_secs(),
+      "milli": now.elapsed().as_millis() as u64,
+      "micro": now.elapsed().as_micros() as u64,
+    },
+    "num_hits": counts.values().sum::<usize>(),
+    "counts": counts,
+    "items": items
+  })))
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![search]
+}
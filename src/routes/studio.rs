@@ -0,0 +1,101 @@
+use super::{apply, open_store, remove_many, search_store, suggest, Filters, SearchForm, SearchResponse};
+use lazy_static::lazy_static;
+use rocket::http::RawStr;
+use rocket::http::Status;
+use rocket_contrib::json::{Json, JsonValue};
+use std::sync::Mutex;
+use std::vec::Vec;
+use twigs::studio::{InputStudio, StoredStudio};
+use twigs::query::Query;
+use twigs::store::Store;
+use twigs::wal::Operation;
+
+lazy_static! {
+  pub static ref STUDIOS: Mutex<Store<StoredStudio>> = Mutex::new(open_store("studio"));
+}
+
+/// Filters of a studio search, besides those of every index
+#[derive(FromForm)]
+struct StudioFilters {
+  parent: Option<String>,
+}
+
+impl Filters for StudioFilters {
+  fn apply(self, query: Query) -> Query {
+    query.equals("parent", self.parent)
+  }
+}
+
+#[put("/<id>", data = "<inputs>")]
+fn update_studio(id: &RawStr, inputs: Json<InputStudio>) -> Result<(), Status> {
+  if inputs.id != id.as_str() {
+    return Err(Status::BadRequest);
+  }
+
+  let mut studios = STUDIOS.lock().unwrap();
+
+  if !studios.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut studios, Operation::Update(id.to_string(), inputs.into_inner()))
+}
+
+#[delete("/<id>")]
+fn delete_studio(id: &RawStr) -> Result<(), Status> {
+  println!("Deleting {}", id.as_str());
+
+  let mut studios = STUDIOS.lock().unwrap();
+
+  if !studios.index().contains(id.as_str()) {
+    return Err(Status::NotFound);
+  }
+  apply(&mut studios, Operation::Remove(id.to_string()))
+}
+
+#[post("/delete", format = "json", data = "<ids>")]
+fn delete_studios(ids: Json<Vec<String>>) -> Result<Json<JsonValue>, Status> {
+  println!("Deleting {} studios", ids.len());
+
+  remove_many(&mut STUDIOS.lock().unwrap(), ids.into_inner())
+}
+
+#[delete("/")]
+fn clear_studios() -> Result<(), Status> {
+  println!("Clearing studio index...");
+
+  apply(&mut STUDIOS.lock().unwrap(), Operation::Clear)
+}
+
+#[get("/info")]
+fn get_studios_info() -> Json<JsonValue> {
+  Json(json!(STUDIOS.lock().unwrap().index().info()))
+}
+
+#[get("/suggest?<query>&<take>")]
+fn suggest_studios(query: String, take: Option<usize>) -> SearchResponse {
+  suggest(STUDIOS.lock().unwrap().index(), &query, take)
+}
+
+#[get("/?<search..>")]
+fn get_studios(search: SearchForm<StudioFilters>) -> SearchResponse {
+  search_store(&STUDIOS, "studios", search)
+}
+
+#[post("/", format = "json", data = "<inputs>")]
+fn create_studios(inputs: Json<Vec<InputStudio>>) -> Result<Json<JsonValue>, Status> {
+  println!("Received new studios");
+
+  let mut studios = STUDIOS.lock().unwrap();
+  apply(&mut studios, Operation::Insert(inputs.into_inner()))?;
+
+  Ok(Json(json!(studios.index().info())))
+}
+
+/// Loads the studio index before the first request comes in
+pub fn load() {
+  lazy_static::initialize(&STUDIOS);
+}
+
+pub fn get_routes() -> Vec<rocket::Route> {
+  routes![get_studios, suggest_studios, create_studios, delete_studio, delete_studios, clear_studios, update_studio, get_studios_info]
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+/// Tuning parameters of the BM25 relevance score.
+///
+/// `k1` controls how quickly repeated terms stop adding to the score,
+/// `b` how strongly long documents are penalized.
+#[derive(Clone, Copy)]
+pub struct Bm25 {
+  pub k1: f32,
+  pub b: f32,
+}
+
+impl Default for Bm25 {
+  fn default() -> Self {
+    Bm25 { k1: 1.2, b: 0.75 }
+  }
+}
+
+impl Bm25 {
+  /// Inverse document frequency of a term contained in `df` of `n` documents
+  pub fn idf(n: usize, df: usize) -> f32 {
+    let n = n as f32;
+    let df = df as f32;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+  }
+
+  /// Score contribution of a term occurring `tf` times in a document of
+  /// length `len`. Occurrences in boosted fields count more than once.
+  pub fn score(&self, idf: f32, tf: f32, len: u32, avg_len: f32) -> f32 {
+    let norm = if avg_len > 0.0 { len as f32 / avg_len } else { 1.0 };
+    idf * tf * (self.k1 + 1.0) / (tf + self.k1 * (1.0 - self.b + self.b * norm))
+  }
+
+  /// Upper bound of `score` for a term, approached as `tf` grows
+  pub fn max_score(&self, idf: f32) -> f32 {
+    idf * (self.k1 + 1.0)
+  }
+}
+
+/// Relevance weight of a match in each text field, by field name.
+///
+/// Aliases of actors and labels use the "alias" weight instead of their
+/// field's. Fields without a weight count once.
+#[derive(Clone)]
+pub struct Boosts(HashMap<String, f32>);
+
+impl Default for Boosts {
+  fn default() -> Self {
+    let weights = [
+      ("name", 3.0),
+      ("actor", 2.0),
+      ("studio", 1.5),
+      ("scene_name", 1.5),
+      ("label", 1.0),
+      ("alias", 0.5),
+    ];
+    Boosts(weights.iter().map(|(field, weight)| (field.to_string(), *weight)).collect())
+  }
+}
+
+impl Boosts {
+  /// No weights, every field counts once
+  pub fn none() -> Boosts {
+    Boosts(HashMap::new())
+  }
+
+  pub fn get(&self, field: &str) -> Option<f32> {
+    self.0.get(field).copied()
+  }
+
+  pub fn set(&mut self, field: &str, weight: f32) {
+    self.0.insert(field.to_string(), weight);
+  }
+
+  /// Fields that have a weight
+  pub fn fields(&self) -> impl Iterator<Item = &str> {
+    self.0.keys().map(|x| x.as_str())
+  }
+}
@@ -0,0 +1,60 @@
+use crate::document::Document;
+use crate::index::Index;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a, T: Document> {
+  seq: u64,
+  index: &'a Index<T>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot<T: Document> {
+  seq: u64,
+  index: Index<T>,
+}
+
+/// Writes the index to `path`, along with the sequence number of the last
+/// logged operation it contains.
+///
+/// The snapshot is written to a temporary file which then replaces the old
+/// snapshot, so a crash while writing never leaves a partial file behind.
+pub fn save<T: Document + Serialize>(index: &Index<T>, seq: u64, path: &Path) -> io::Result<()> {
+  let dir = path.parent().unwrap_or_else(|| Path::new("."));
+  fs::create_dir_all(dir)?;
+
+  let tmp_path = path.with_extension("tmp");
+  let mut writer = BufWriter::new(File::create(&tmp_path)?);
+  serde_json::to_writer(&mut writer, &SnapshotRef { seq, index })?;
+  let file = writer.into_inner().map_err(|err| err.into_error())?;
+  file.sync_all()?;
+  drop(file);
+
+  fs::rename(&tmp_path, path)?;
+  sync_dir(dir)
+}
+
+/// Reads a snapshot written by `save`, returns None if there is none yet
+pub fn load<T: Document + DeserializeOwned>(path: &Path) -> io::Result<Option<(Index<T>, u64)>> {
+  if !path.exists() {
+    return Ok(None);
+  }
+  let reader = BufReader::new(File::open(path)?);
+  let snapshot: Snapshot<T> = serde_json::from_reader(reader)?;
+  Ok(Some((snapshot.index, snapshot.seq)))
+}
+
+/// Makes a rename inside `dir` durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+  File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+  Ok(())
+}
@@ -0,0 +1,257 @@
+use crate::document::Document;
+use crate::index::Index;
+use crate::scoring::{Bm25, Boosts};
+use crate::snapshot;
+use crate::wal::{Operation, Wal};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An index backed by a snapshot and a write-ahead log.
+///
+/// Every operation is appended to the log before it is applied. Once
+/// `snapshot_interval` operations have been logged, a new snapshot is
+/// written and the log is truncated.
+pub struct Store<T: Document> {
+  index: Index<T>,
+  seq: u64,
+  pending: usize,
+  snapshot_interval: usize,
+  snapshot_path: PathBuf,
+  wal: Wal,
+}
+
+impl<T> Store<T>
+where
+  T: Document + Serialize + DeserializeOwned,
+  T::Input: Serialize + DeserializeOwned,
+{
+  /// Loads the last snapshot of `name` in `dir` and replays the log on top
+  pub fn open(dir: &Path, name: &str, snapshot_interval: usize) -> io::Result<Store<T>> {
+    fs::create_dir_all(dir)?;
+
+    let snapshot_path = dir.join(format!("{}.json", name));
+    let wal_path = dir.join(format!("{}.wal", name));
+
+    let (mut index, mut seq) = snapshot::load(&snapshot_path)?.unwrap_or_else(|| (Index::new(), 0));
+
+    // Entries up to the snapshot's sequence number are already part of it,
+    // they are left over if we crashed before the log was truncated.
+    let mut pending = 0;
+    for (entry_seq, op) in Wal::read(&wal_path)? {
+      if entry_seq > seq {
+        apply(&mut index, op);
+        seq = entry_seq;
+        pending += 1;
+      }
+    }
+
+    Ok(Store {
+      index,
+      seq,
+      pending,
+      snapshot_interval,
+      snapshot_path,
+      wal: Wal::open(&wal_path)?,
+    })
+  }
+
+  pub fn index(&self) -> &Index<T> {
+    &self.index
+  }
+
+  pub fn set_bm25(&mut self, bm25: Bm25) {
+    self.index.set_bm25(bm25);
+  }
+
+  pub fn set_boosts(&mut self, boosts: Boosts) {
+    self.index.set_boosts(boosts);
+  }
+
+  /// Logs an operation, then applies it to the index.
+  ///
+  /// Once this returns Ok, the operation survives a crash.
+  pub fn apply(&mut self, op: Operation<T::Input>) -> io::Result<()> {
+    self.wal.append(self.seq + 1, &op)?;
+    self.seq += 1;
+    self.pending += 1;
+    apply(&mut self.index, op);
+
+    if self.pending >= self.snapshot_interval {
+      if let Err(err) = self.snapshot() {
+        println!("Failed to write {}: {}", self.snapshot_path.display(), err);
+      }
+    }
+    Ok(())
+  }
+
+  /// Writes a snapshot of the index and truncates the log
+  pub fn snapshot(&mut self) -> io::Result<()> {
+    snapshot::save(&self.index, self.seq, &self.snapshot_path)?;
+    self.wal.truncate()?;
+    self.pending = 0;
+    Ok(())
+  }
+}
+
+fn apply<T: Document>(index: &mut Index<T>, op: Operation<T::Input>) {
+  match op {
+    Operation::Insert(inputs) => {
+      for input in inputs.iter() {
+        index.insert(input);
+      }
+    }
+    Operation::Update(id, input) => {
+      index.update(&id, &input);
+    }
+    Operation::Remove(id) => {
+      index.remove(&id);
+    }
+    Operation::RemoveMany(ids) => {
+      index.remove_many(&ids);
+    }
+    Operation::Clear => index.clear(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::studio::{InputStudio, StoredStudio};
+  use std::fs::OpenOptions;
+  use std::io::Write;
+
+  /// Empty data directory unique to a test
+  fn data_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("twigs-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  fn studio(id: &str, name: &str) -> InputStudio {
+    InputStudio {
+      id: id.to_string(),
+      name: name.to_string(),
+      added_on: 0,
+      aliases: Vec::new(),
+      bookmark: None,
+      favorite: false,
+      rating: None,
+      parent: None,
+      num_scenes: 0,
+    }
+  }
+
+  fn open(dir: &Path, interval: usize) -> Store<StoredStudio> {
+    Store::open(dir, "studio", interval).unwrap()
+  }
+
+  #[test]
+  fn reopen_replays_log() {
+    let dir = data_dir("replay");
+    let mut store = open(&dir, 1000);
+    store
+      .apply(Operation::Insert(vec![studio("st1", "Vixen"), studio("st2", "Blacked"), studio("st3", "Tushy")]))
+      .unwrap();
+    store.apply(Operation::Update("st1".into(), studio("st1", "Vixen Media"))).unwrap();
+    store.apply(Operation::Remove("st2".into())).unwrap();
+    store.apply(Operation::RemoveMany(vec!["st3".into(), "unknown".into()])).unwrap();
+    drop(store);
+
+    let store = open(&dir, 1000);
+    assert_eq!(store.index().len(), 1);
+    assert!(store.index().contains("st1"));
+    assert_eq!(store.seq, 4);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn reopen_loads_snapshot_then_log() {
+    let dir = data_dir("snapshot");
+    let mut store = open(&dir, 2);
+    store.apply(Operation::Insert(vec![studio("st1", "Vixen")])).unwrap();
+    store.apply(Operation::Insert(vec![studio("st2", "Blacked")])).unwrap();
+    store.apply(Operation::Insert(vec![studio("st3", "Tushy")])).unwrap();
+    assert_eq!(store.pending, 1);
+    drop(store);
+
+    let store = open(&dir, 2);
+    assert_eq!(store.index().len(), 3);
+    assert_eq!(store.seq, 3);
+    assert_eq!(store.pending, 1);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn torn_tail_is_discarded_before_appending() {
+    let dir = data_dir("torn");
+    let mut store = open(&dir, 1000);
+    store.apply(Operation::Insert(vec![studio("st1", "Vixen")])).unwrap();
+    drop(store);
+
+    // A crash in the middle of writing an entry
+    let mut wal = OpenOptions::new().append(true).open(dir.join("studio.wal")).unwrap();
+    wal.write_all(br#"{"seq":2,"op":{"Ins"#).unwrap();
+    drop(wal);
+
+    let mut store = open(&dir, 1000);
+    assert_eq!(store.index().len(), 1);
+    store.apply(Operation::Insert(vec![studio("st2", "Blacked")])).unwrap();
+    drop(store);
+
+    let mut store = open(&dir, 1000);
+    assert!(store.index().contains("st2"));
+    store.apply(Operation::Insert(vec![studio("st3", "Tushy")])).unwrap();
+    drop(store);
+
+    let store = open(&dir, 1000);
+    assert_eq!(store.index().len(), 3);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn failed_append_is_rolled_back() {
+    let dir = data_dir("rollback");
+    let mut store = open(&dir, 1000);
+    store.apply(Operation::Insert(vec![studio("st1", "Vixen")])).unwrap();
+
+    // The entry reaches the file but is never synced
+    store.wal.fail_sync = true;
+    assert!(store.apply(Operation::Remove("st1".into())).is_err());
+    assert!(store.index().contains("st1"));
+    assert_eq!(store.seq, 1);
+
+    store.wal.fail_sync = false;
+    store.apply(Operation::Insert(vec![studio("st2", "Blacked")])).unwrap();
+    drop(store);
+
+    let store = open(&dir, 1000);
+    assert!(store.index().contains("st1"));
+    assert!(store.index().contains("st2"));
+    assert_eq!(store.seq, 2);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn entry_without_newline_is_discarded() {
+    let dir = data_dir("newline");
+    let mut store = open(&dir, 1000);
+    store.apply(Operation::Insert(vec![studio("st1", "Vixen")])).unwrap();
+    drop(store);
+
+    let mut wal = OpenOptions::new().append(true).open(dir.join("studio.wal")).unwrap();
+    wal.write_all(br#"{"seq":2,"op":{"Remove":"st1"}}"#).unwrap();
+    drop(wal);
+
+    let mut store = open(&dir, 1000);
+    assert!(store.index().contains("st1"));
+    store.apply(Operation::Insert(vec![studio("st2", "Blacked")])).unwrap();
+    drop(store);
+
+    let store = open(&dir, 1000);
+    assert_eq!(store.index().len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,88 @@
+use crate::document::{Document, Field, Text};
+use crate::index::Index;
+use std::vec::Vec;
+
+pub type StudioIndex = Index<StoredStudio>;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputStudio {
+  pub id: String,
+  pub name: String,
+  pub added_on: i64,
+  pub aliases: Vec<String>,
+  pub bookmark: Option<i64>,
+  pub favorite: bool,
+  pub rating: Option<u8>,
+  pub parent: Option<String>,
+  pub num_scenes: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredStudio {
+  pub id: String,
+  pub name: String,
+  pub added_on: i64,
+  pub bookmark: Option<i64>,
+  pub favorite: bool,
+  pub rating: Option<u8>,
+  pub parent: Option<String>,
+  pub num_scenes: u32,
+}
+
+impl Document for StoredStudio {
+  type Input = InputStudio;
+
+  const TEXT_FIELDS: &'static [&'static str] = &["name"];
+  const FIELDS: &'static [&'static str] = &[
+    "name",
+    "alpha",
+    "added_on",
+    "addedOn",
+    "bookmark",
+    "favorite",
+    "rating",
+    "parent",
+    "num_scenes",
+    "numScenes",
+  ];
+
+  fn from_input(input: &InputStudio) -> StoredStudio {
+    StoredStudio {
+      id: input.id.clone(),
+      name: input.name.clone(),
+      added_on: input.added_on,
+      bookmark: input.bookmark,
+      favorite: input.favorite,
+      rating: input.rating,
+      parent: input.parent.clone(),
+      num_scenes: input.num_scenes,
+    }
+  }
+
+  fn input_id(input: &InputStudio) -> &str {
+    &input.id
+  }
+
+  fn id(&self) -> &str {
+    &self.id
+  }
+
+  fn text(input: &InputStudio) -> Vec<Text> {
+    let mut text = vec![Text::new("name", &input.name)];
+    text.extend(input.aliases.iter().map(|x| Text::alias("name", x)));
+    text
+  }
+
+  fn field(&self, name: &str) -> Option<Field> {
+    match name {
+      "name" | "alpha" => Some(Field::Text(&self.name)),
+      "added_on" | "addedOn" => Some(Field::Number(Some(self.added_on))),
+      "bookmark" => Some(Field::Number(self.bookmark)),
+      "favorite" => Some(Field::Flag(self.favorite)),
+      "rating" => Some(Field::Number(self.rating.map(i64::from))),
+      "parent" => Some(Field::Ref(self.parent.as_deref())),
+      "num_scenes" | "numScenes" => Some(Field::Number(Some(i64::from(self.num_scenes)))),
+      _ => None,
+    }
+  }
+}
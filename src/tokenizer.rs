@@ -0,0 +1,108 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use rust_stemmers::{Algorithm, Stemmer};
+use std::vec::Vec;
+
+lazy_static! {
+  static ref ALPHANUMERIC: Regex = Regex::new(r"[a-zA-Z0-9]+").unwrap();
+  static ref STEMMER: Stemmer = Stemmer::create(Algorithm::English);
+}
+
+/// A word of a string, with its byte range in the original text
+pub struct Span {
+  pub position: u32,
+  pub start: usize,
+  pub end: usize,
+  /// Lowercase word
+  pub word: String,
+}
+
+/// Splits a string into lowercase words along with their position and
+/// where they are in `s`. Words shorter than three characters are dropped,
+/// but still take up a position, so "anna de bell" does not contain the
+/// phrase "anna bell".
+pub fn spans(s: &str) -> Vec<Span> {
+  ALPHANUMERIC
+    .find_iter(s)
+    .enumerate()
+    .filter(|(_, x)| x.as_str().len() > 2)
+    .map(|(i, x)| Span {
+      position: i as u32,
+      start: x.start(),
+      end: x.end(),
+      word: x.as_str().to_lowercase(),
+    })
+    .collect()
+}
+
+/// Splits a string into lowercase words along with their position, see `spans`
+pub fn positioned_words(s: &str) -> Vec<(u32, String)> {
+  spans(s).into_iter().map(|x| (x.position, x.word)).collect()
+}
+
+/// Splits a string into lowercase words.
+/// Words shorter than three characters are dropped.
+pub fn words(s: &str) -> Vec<String> {
+  positioned_words(s).into_iter().map(|(_, x)| x).collect()
+}
+
+/// The lowercase word at the very end of `s`, which may still be being
+/// typed. Unlike `words`, words of two characters are kept.
+pub fn partial_word(s: &str) -> Option<String> {
+  let word = s.rsplit(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or("");
+  Some(word.to_lowercase()).filter(|x| x.len() > 1)
+}
+
+pub fn stem(word: &str) -> String {
+  STEMMER.stem(word).into_owned()
+}
+
+/// Splits a string into lowercase, stemmed tokens
+pub fn tokenize(s: &str) -> Vec<String> {
+  words(s).iter().map(|x| stem(x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn spans_keep_positions_and_offsets() {
+    let spans = spans("Anna de Bell");
+    let words: Vec<(u32, usize, usize, &str)> = spans
+      .iter()
+      .map(|x| (x.position, x.start, x.end, x.word.as_str()))
+      .collect();
+    assert_eq!(words, vec![(0, 0, 4, "anna"), (2, 8, 12, "bell")]);
+  }
+
+  #[test]
+  fn spans_offsets_skip_non_ascii() {
+    let text = "Café Blonde";
+    let spans = spans(text);
+    assert_eq!(spans.len(), 2);
+    assert_eq!(&text[spans[1].start..spans[1].end], "Blonde");
+  }
+
+  #[test]
+  fn partial_word_takes_last_word() {
+    assert_eq!(partial_word("blonde be"), Some("be".to_string()));
+    assert_eq!(partial_word("anna-Bel"), Some("bel".to_string()));
+    assert_eq!(partial_word("blonde b"), None);
+    assert_eq!(partial_word(""), None);
+  }
+
+  #[test]
+  fn partial_word_handles_non_ascii() {
+    assert_eq!(partial_word("dé"), None);
+    assert_eq!(partial_word("é"), None);
+    assert_eq!(partial_word("né"), None);
+    assert_eq!(partial_word("café ab"), Some("ab".to_string()));
+    assert_eq!(partial_word("éab"), Some("ab".to_string()));
+  }
+
+  #[test]
+  fn tokenize_stems_and_drops_short_words() {
+    assert_eq!(tokenize("Blondes at the beach"), vec!["blond", "the", "beach"]);
+  }
+}
@@ -0,0 +1,120 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::vec::Vec;
+
+/// A change to an index, as recorded in the write-ahead log
+#[derive(Serialize, Deserialize)]
+pub enum Operation<I> {
+  Insert(Vec<I>),
+  Update(String, I),
+  Remove(String),
+  RemoveMany(Vec<String>),
+  Clear,
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a, I> {
+  seq: u64,
+  op: &'a Operation<I>,
+}
+
+#[derive(Deserialize)]
+struct Entry<I> {
+  seq: u64,
+  op: Operation<I>,
+}
+
+/// Append-only log of operations, one JSON entry per line
+pub struct Wal {
+  file: File,
+  // Set when a failed append could not be rolled back, the end of the log
+  // is unknown so nothing may be appended anymore
+  broken: bool,
+  #[cfg(test)]
+  pub(crate) fail_sync: bool,
+}
+
+impl Wal {
+  pub fn open(path: &Path) -> io::Result<Wal> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(Wal {
+      file,
+      broken: false,
+      #[cfg(test)]
+      fail_sync: false,
+    })
+  }
+
+  /// Reads all entries of a log as (sequence number, operation) pairs.
+  ///
+  /// An incomplete last line is what a crash during `append` leaves
+  /// behind, that operation was never acknowledged. It is cut off, so the
+  /// next entry does not get appended onto it.
+  pub fn read<I: DeserializeOwned>(path: &Path) -> io::Result<Vec<(u64, Operation<I>)>> {
+    if !path.exists() {
+      return Ok(Vec::new());
+    }
+
+    let data = fs::read(path)?;
+    let mut entries = Vec::new();
+    // End of the last complete entry
+    let mut valid = 0;
+    while let Some(len) = data[valid..].iter().position(|x| *x == b'\n') {
+      let end = valid + len;
+      match serde_json::from_slice::<Entry<I>>(&data[valid..end]) {
+        Ok(entry) => entries.push((entry.seq, entry.op)),
+        Err(_) if end + 1 == data.len() => break,
+        Err(err) => return Err(err.into()),
+      }
+      valid = end + 1;
+    }
+
+    if valid < data.len() {
+      println!("Discarding incomplete entry at the end of {}", path.display());
+      let file = OpenOptions::new().write(true).open(path)?;
+      file.set_len(valid as u64)?;
+      file.sync_all()?;
+    }
+    Ok(entries)
+  }
+
+  /// Appends an operation and waits until it is on disk.
+  ///
+  /// On error the log is cut back to where it was, so the operation that
+  /// was not acknowledged is not replayed later either.
+  pub fn append<I: Serialize>(&mut self, seq: u64, op: &Operation<I>) -> io::Result<()> {
+    if self.broken {
+      return Err(io::Error::new(io::ErrorKind::Other, "Write-ahead log is broken by a failed append"));
+    }
+
+    let mut line = serde_json::to_vec(&EntryRef { seq, op })?;
+    line.push(b'\n');
+    let len = self.file.metadata()?.len();
+    if let Err(err) = self.write(&line) {
+      if self.file.set_len(len).and_then(|_| self.file.sync_all()).is_err() {
+        self.broken = true;
+      }
+      return Err(err);
+    }
+    Ok(())
+  }
+
+  fn write(&mut self, line: &[u8]) -> io::Result<()> {
+    self.file.write_all(line)?;
+    #[cfg(test)]
+    {
+      if self.fail_sync {
+        return Err(io::Error::new(io::ErrorKind::Other, "Injected sync failure"));
+      }
+    }
+    self.file.sync_data()
+  }
+
+  pub fn truncate(&mut self) -> io::Result<()> {
+    self.file.set_len(0)?;
+    self.file.sync_all()
+  }
+}